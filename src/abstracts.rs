@@ -0,0 +1,218 @@
+use crate::deser::{OneOrMany, text_content};
+use crate::exchange::{Exchange, Single};
+use crate::ops_client::ClientCore;
+use crate::{OpsError, OpsResponse, PatentDetails, PatentReferenceType, PublicationConstituents};
+use serde::Deserialize;
+use serde_json::Value;
+
+/// The abstract of a publication in one language.
+#[derive(Debug)]
+pub struct Abstract {
+    pub publication: Option<PatentDetails>,
+    pub language: Option<String>,
+    /// Where the abstract comes from, e.g. "national office" or "EPO", when OPS says.
+    pub source: Option<String>,
+    pub paragraphs: Vec<String>,
+}
+
+impl Abstract {
+    /// All of the paragraphs, one per line.
+    pub fn text(&self) -> String {
+        self.paragraphs.join("\n")
+    }
+
+    /// Picks the abstract in the first of `languages` (e.g. `["en", "de", "fr"]`) there is one
+    /// in, falling back to the first abstract when none of them match.
+    pub fn preferred<'a>(abstracts: &'a [Abstract], languages: &[&str]) -> Option<&'a Abstract> {
+        preferred_index(abstracts, languages).map(|i| &abstracts[i])
+    }
+}
+
+fn preferred_index(abstracts: &[Abstract], languages: &[&str]) -> Option<usize> {
+    languages
+        .iter()
+        .find_map(|language| {
+            abstracts.iter().position(|a| {
+                a.language
+                    .as_deref()
+                    .is_some_and(|l| l.eq_ignore_ascii_case(language))
+            })
+        })
+        .or_else(|| (!abstracts.is_empty()).then_some(0))
+}
+
+/* Abstract structs
+ *  These are for deserialising the JSON from published-data retrieval with the abstract
+ *  constituent.
+ */
+
+#[derive(Debug, Deserialize)]
+struct AbsAbstract {
+    #[serde(rename(deserialize = "@lang"))]
+    lang: Option<String>,
+    #[serde(rename(deserialize = "@abstract-source"))]
+    abstract_source: Option<String>,
+    #[serde(default)]
+    p: OneOrMany<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AbsExchangeDocument {
+    #[serde(rename(deserialize = "@country"))]
+    country: Option<String>,
+    #[serde(rename(deserialize = "@doc-number"))]
+    doc_number: Option<String>,
+    #[serde(rename(deserialize = "@kind"))]
+    kind: Option<String>,
+    #[serde(rename(deserialize = "abstract"), default)]
+    abstracts: OneOrMany<AbsAbstract>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AbsExchangeDocuments {
+    #[serde(rename(deserialize = "exchange-document"), default)]
+    exchange_document: OneOrMany<AbsExchangeDocument>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AbsWorldPatentData {
+    #[serde(rename(deserialize = "exchange-documents"), default)]
+    exchange_documents: OneOrMany<AbsExchangeDocuments>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AbsResult {
+    #[serde(rename(deserialize = "ops:world-patent-data"))]
+    ops_world_patent_data: AbsWorldPatentData,
+}
+
+fn publication_of(document: &AbsExchangeDocument) -> Option<PatentDetails> {
+    Some(PatentDetails {
+        country_code: document.country.clone()?,
+        number: document.doc_number.clone()?,
+        kind_code: document.kind.clone(),
+        date: None,
+        reference_type: PatentReferenceType::Publication,
+    })
+}
+
+/// One `Abstract` per language returned, for every publication in the response.
+pub(crate) fn parse_abstracts(response: &OpsResponse) -> Result<Vec<Abstract>, OpsError> {
+    let result: AbsResult = response.json()?;
+    let mut abstracts = Vec::new();
+    let documents = result
+        .ops_world_patent_data
+        .exchange_documents
+        .into_vec()
+        .into_iter()
+        .flat_map(|d| d.exchange_document.into_vec());
+    for document in documents {
+        abstracts.extend(document_abstracts(document));
+    }
+    Ok(abstracts)
+}
+
+fn document_abstracts(document: AbsExchangeDocument) -> Vec<Abstract> {
+    let publication = publication_of(&document);
+    document
+        .abstracts
+        .into_vec()
+        .into_iter()
+        .map(|r#abstract| Abstract {
+            publication: publication.clone(),
+            language: r#abstract.lang,
+            source: r#abstract.abstract_source,
+            paragraphs: r#abstract
+                .p
+                .into_vec()
+                .iter()
+                .map(|p| text_content(p).trim().to_string())
+                .filter(|p| !p.is_empty())
+                .collect(),
+        })
+        .collect()
+}
+
+/// The abstracts in one `exchange-document`, e.g. one that came with another service's response.
+pub(crate) fn exchange_document_abstracts(
+    exchange_document: &Value,
+) -> Result<Vec<Abstract>, OpsError> {
+    Ok(document_abstracts(AbsExchangeDocument::deserialize(
+        exchange_document,
+    )?))
+}
+
+/// Takes the abstract `Abstract::preferred` picks out of `abstracts`.
+pub(crate) fn preferred_abstract(
+    mut abstracts: Vec<Abstract>,
+    languages: &[&str],
+) -> Option<Abstract> {
+    let index = preferred_index(&abstracts, languages)?;
+    Some(abstracts.swap_remove(index))
+}
+
+impl ClientCore {
+    pub(crate) fn get_abstracts_response(
+        &self,
+        patent_details: &PatentDetails,
+    ) -> Result<Single, OpsError> {
+        Ok(Single::new(self.publication_request(
+            patent_details,
+            &[PublicationConstituents::Abstract],
+        )?))
+    }
+
+    pub(crate) fn get_abstracts(
+        &self,
+        patent_details: &PatentDetails,
+    ) -> Result<impl Exchange<Output = Vec<Abstract>>, OpsError> {
+        Ok(self
+            .get_abstracts_response(patent_details)?
+            .map(|response| parse_abstracts(&response)))
+    }
+
+    /// Built on the same response as `get_abstracts`.
+    pub(crate) fn get_abstract(
+        &self,
+        patent_details: &PatentDetails,
+        languages: &[&str],
+    ) -> Result<impl Exchange<Output = Option<Abstract>>, OpsError> {
+        Ok(self
+            .get_abstracts_response(patent_details)?
+            .map(move |response| Ok(preferred_abstract(parse_abstracts(&response)?, languages))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::response::fixture_response;
+
+    #[test]
+    fn test_abstract_deser() {
+        let response = fixture_response("test/example_abstract.json");
+        let abstracts = parse_abstracts(&response).unwrap();
+        assert_eq!(abstracts.len(), 2);
+        let english = &abstracts[0];
+        assert_eq!(
+            english.publication.as_ref().unwrap().to_string(),
+            "WO2006017341A2"
+        );
+        assert_eq!(english.language.as_deref(), Some("en"));
+        assert_eq!(english.source.as_deref(), Some("national office"));
+        assert_eq!(english.paragraphs.len(), 2);
+        assert_eq!(
+            english.paragraphs[1],
+            "The blocks are coded using H.264 prediction modes."
+        );
+
+        let preferred = |languages: &[&str]| {
+            Abstract::preferred(&abstracts, languages)
+                .and_then(|a| a.language.as_deref())
+                .map(str::to_string)
+        };
+        assert_eq!(preferred(&["de", "FR", "en"]).as_deref(), Some("fr"));
+        assert_eq!(preferred(&["de"]).as_deref(), Some("en"));
+        assert!(Abstract::preferred(&[], &["en"]).is_none());
+    }
+}
@@ -0,0 +1,553 @@
+use crate::bulk::BulkResults;
+use crate::exchange::Exchange;
+use crate::images::{
+    ImageFormat, ImageInquiry, full_document_page_path, full_document_path, merge_pdfs,
+    no_full_document,
+};
+use crate::number::NumberFormat;
+use crate::ops_client::{ClientCore, NeedsAuth, OpsRequest};
+use crate::{
+    Abstract, Claims, ClassificationMapping, ClassificationScheme, CpcClassification, CpcDepth,
+    CpcSearchResult, Description, EpoOpsCredentials, Equivalent, EquivalentsConstituents, Family,
+    FamilyConstituents, FullCycle, FulltextInquiry, LegalEvent, OpsClientSettings, OpsError,
+    OpsResponse, PatentDetails, PublicationConstituents, PublishedSearchResults, QuotaStatus,
+    RegisterConstituents, ResponseFormat, Usage, UsageReport,
+};
+use chrono::naive::NaiveDate;
+use log::debug;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::fs;
+use tokio::sync::Mutex;
+
+/// The async version of `OpsClient`, for use from within a tokio runtime.
+///
+/// Every method does what the `OpsClient` method of the same name does - requests are built and
+/// responses read by the same code, only the sending and waiting differ.
+pub struct AsyncOpsClient {
+    core: ClientCore,
+    http_client: reqwest::Client,
+    // As in OpsClient, but a tokio Mutex as it's held across the token request
+    token_refresh: Arc<Mutex<()>>,
+}
+
+impl AsyncOpsClient {
+    pub fn new(
+        credentials: EpoOpsCredentials,
+        settings: OpsClientSettings,
+    ) -> Result<AsyncOpsClient, OpsError> {
+        let http_settings = &settings.http;
+        let mut builder = reqwest::ClientBuilder::new()
+            .redirect(reqwest::redirect::Policy::none())
+            .user_agent(&http_settings.user_agent)
+            .gzip(http_settings.gzip);
+        if let Some(connect_timeout) = http_settings.connect_timeout {
+            builder = builder.connect_timeout(connect_timeout);
+        }
+        if let Some(read_timeout) = http_settings.read_timeout {
+            builder = builder.read_timeout(read_timeout);
+        }
+        if let Some(proxy) = http_settings.reqwest_proxy()? {
+            builder = builder.proxy(proxy);
+        }
+        let http_client = builder.build()?;
+        Ok(AsyncOpsClient {
+            core: ClientCore::new(credentials, settings)?,
+            http_client,
+            token_refresh: Arc::default(),
+        })
+    }
+
+    pub fn with_response_format(&self, response_format: ResponseFormat) -> AsyncOpsClient {
+        AsyncOpsClient {
+            core: self.core.with_response_format(response_format),
+            http_client: self.http_client.clone(),
+            token_refresh: Arc::clone(&self.token_refresh),
+        }
+    }
+
+    pub fn settings(&self) -> &OpsClientSettings {
+        &self.core.settings
+    }
+
+    pub fn quota_status(&self) -> QuotaStatus {
+        self.core.quota_status()
+    }
+
+    async fn execute(&self, request: OpsRequest) -> Result<OpsResponse, OpsError> {
+        let mut attempt = 1;
+        loop {
+            match self.execute_once(&request).await {
+                Err(e) => match self.core.retry_delay(&request, attempt, &e) {
+                    Some(delay) => {
+                        tokio::time::sleep(delay).await;
+                        attempt += 1;
+                    }
+                    None => return Err(e),
+                },
+                result => return result,
+            }
+        }
+    }
+
+    async fn run<E: Exchange>(&self, mut exchange: E) -> Result<E::Output, OpsError> {
+        while let Some(request) = exchange.next_request()? {
+            exchange.handle(self.execute(request).await)?;
+        }
+        exchange.finish()
+    }
+
+    async fn execute_once(&self, request: &OpsRequest) -> Result<OpsResponse, OpsError> {
+        match request.needs_auth {
+            NeedsAuth::No => self.send(request, None).await,
+            NeedsAuth::Yes => {
+                let auth_token = self.get_auth_token().await?;
+                match self.send(request, Some(&auth_token)).await {
+                    Err(OpsError::Auth { .. }) => {
+                        debug!(target: "authentication", "Access token rejected, re-authenticating");
+                        self.core.token.invalidate(&auth_token);
+                        let auth_token = self.get_auth_token().await?;
+                        self.send(request, Some(&auth_token)).await
+                    }
+                    result => result,
+                }
+            }
+        }
+    }
+
+    async fn send(
+        &self,
+        request: &OpsRequest,
+        auth_token: Option<&str>,
+    ) -> Result<OpsResponse, OpsError> {
+        let mut builder = self
+            .http_client
+            .request(request.method.clone(), &request.url)
+            .headers(request.headers.clone());
+        if let Some(auth_token) = auth_token {
+            builder = builder.bearer_auth(auth_token);
+        }
+        if let Some(body) = &request.body {
+            builder = builder.body(body.clone());
+        }
+
+        let wait = self.core.before_send(request)?;
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+        let sent_at = Instant::now();
+        let resp = builder.send().await?;
+        let status = resp.status();
+        let headers = resp.headers().clone();
+        let body = resp.bytes().await?.to_vec();
+        let response = OpsResponse::new(status, headers, body, &request.url, sent_at.elapsed());
+        self.core.handle_response(request, response)
+    }
+
+    pub async fn get_auth_token(&self) -> Result<String, OpsError> {
+        if let Some(auth_token) = self.core.token.valid_token() {
+            debug!(target: "authentication", "Used previous authentication token");
+            return Ok(auth_token);
+        }
+        let _refreshing = self.token_refresh.lock().await;
+        if let Some(auth_token) = self.core.token.valid_token() {
+            debug!(target: "authentication", "Used token refreshed by another caller");
+            return Ok(auth_token);
+        }
+
+        debug!(target: "authentication", "Need new authentication token");
+        let requested_at = Instant::now();
+        let response = Box::pin(self.execute(self.core.token_request())).await;
+        self.core.store_token(response, requested_at)
+    }
+
+    pub async fn get_publication_bulk(
+        &self,
+        patents_details: &[PatentDetails],
+        constituents: &[PublicationConstituents],
+    ) -> Result<BulkResults<Vec<Value>>, OpsError> {
+        self.run(
+            self.core
+                .get_publication_bulk(patents_details, constituents),
+        )
+        .await
+    }
+
+    pub async fn get_publication(
+        &self,
+        patent_details: &PatentDetails,
+        constituents: &[PublicationConstituents],
+    ) -> Result<OpsResponse, OpsError> {
+        self.execute(
+            self.core
+                .publication_request(patent_details, constituents)?,
+        )
+        .await
+    }
+
+    pub async fn get_abstracts(
+        &self,
+        patent_details: &PatentDetails,
+    ) -> Result<Vec<Abstract>, OpsError> {
+        self.run(self.core.get_abstracts(patent_details)?).await
+    }
+
+    pub async fn get_abstracts_response(
+        &self,
+        patent_details: &PatentDetails,
+    ) -> Result<OpsResponse, OpsError> {
+        self.run(self.core.get_abstracts_response(patent_details)?)
+            .await
+    }
+
+    pub async fn get_abstract(
+        &self,
+        patent_details: &PatentDetails,
+        languages: &[&str],
+    ) -> Result<Option<Abstract>, OpsError> {
+        self.run(self.core.get_abstract(patent_details, languages)?)
+            .await
+    }
+
+    pub async fn get_full_cycle(
+        &self,
+        patent_details: &PatentDetails,
+    ) -> Result<FullCycle, OpsError> {
+        self.run(self.core.get_full_cycle(patent_details)?).await
+    }
+
+    pub async fn get_full_cycle_response(
+        &self,
+        patent_details: &PatentDetails,
+    ) -> Result<OpsResponse, OpsError> {
+        self.run(self.core.get_full_cycle_response(patent_details)?)
+            .await
+    }
+
+    pub async fn search_register(&self, query_string: &str) -> Result<Vec<OpsResponse>, OpsError> {
+        self.run(self.core.search_register(query_string)).await
+    }
+
+    pub async fn search_published(
+        &self,
+        query_string: &str,
+        constituents: &[PublicationConstituents],
+    ) -> Result<PublishedSearchResults, OpsError> {
+        self.run(self.core.search_published(query_string, constituents))
+            .await
+    }
+
+    pub async fn search_published_response(
+        &self,
+        query_string: &str,
+        constituents: &[PublicationConstituents],
+    ) -> Result<Vec<OpsResponse>, OpsError> {
+        self.run(
+            self.core
+                .search_published_response(query_string, constituents),
+        )
+        .await
+    }
+
+    pub async fn get_register_info(
+        &self,
+        patent_details: &PatentDetails,
+        constituents: &[RegisterConstituents],
+    ) -> Result<OpsResponse, OpsError> {
+        self.execute(self.core.register_request(patent_details, constituents)?)
+            .await
+    }
+
+    pub async fn get_register_info_bulk(
+        &self,
+        patents_details: &[PatentDetails],
+        constituents: &[RegisterConstituents],
+    ) -> Result<BulkResults<Value>, OpsError> {
+        self.run(
+            self.core
+                .get_register_info_bulk(patents_details, constituents),
+        )
+        .await
+    }
+
+    pub async fn get_family(
+        &self,
+        patent_details: &PatentDetails,
+        constituents: &[FamilyConstituents],
+    ) -> Result<Family, OpsError> {
+        self.run(self.core.get_family(patent_details, constituents)?)
+            .await
+    }
+
+    pub async fn get_family_response(
+        &self,
+        patent_details: &PatentDetails,
+        constituents: &[FamilyConstituents],
+    ) -> Result<OpsResponse, OpsError> {
+        self.run(
+            self.core
+                .get_family_response(patent_details, constituents)?,
+        )
+        .await
+    }
+
+    pub async fn get_equivalents(
+        &self,
+        patent_details: &PatentDetails,
+        constituents: &[EquivalentsConstituents],
+    ) -> Result<Vec<Equivalent>, OpsError> {
+        self.run(self.core.get_equivalents(patent_details, constituents)?)
+            .await
+    }
+
+    pub async fn get_equivalents_response(
+        &self,
+        patent_details: &PatentDetails,
+        constituents: &[EquivalentsConstituents],
+    ) -> Result<OpsResponse, OpsError> {
+        self.run(
+            self.core
+                .get_equivalents_response(patent_details, constituents)?,
+        )
+        .await
+    }
+
+    pub async fn get_fulltext_inquiry(
+        &self,
+        patent_details: &PatentDetails,
+    ) -> Result<FulltextInquiry, OpsError> {
+        self.run(self.core.get_fulltext_inquiry(patent_details)?)
+            .await
+    }
+
+    pub async fn get_fulltext_inquiry_response(
+        &self,
+        patent_details: &PatentDetails,
+    ) -> Result<OpsResponse, OpsError> {
+        self.run(self.core.get_fulltext_inquiry_response(patent_details)?)
+            .await
+    }
+
+    pub async fn get_description(
+        &self,
+        patent_details: &PatentDetails,
+    ) -> Result<Vec<Description>, OpsError> {
+        self.run(self.core.get_description(patent_details)?).await
+    }
+
+    pub async fn get_description_response(
+        &self,
+        patent_details: &PatentDetails,
+    ) -> Result<OpsResponse, OpsError> {
+        self.run(self.core.get_description_response(patent_details)?)
+            .await
+    }
+
+    pub async fn get_claims(
+        &self,
+        patent_details: &PatentDetails,
+    ) -> Result<Vec<Claims>, OpsError> {
+        self.run(self.core.get_claims(patent_details)?).await
+    }
+
+    pub async fn get_claims_response(
+        &self,
+        patent_details: &PatentDetails,
+    ) -> Result<OpsResponse, OpsError> {
+        self.run(self.core.get_claims_response(patent_details)?)
+            .await
+    }
+
+    pub async fn get_image_inquiry(
+        &self,
+        patent_details: &PatentDetails,
+    ) -> Result<ImageInquiry, OpsError> {
+        self.run(self.core.get_image_inquiry(patent_details)?).await
+    }
+
+    pub async fn get_image_inquiry_response(
+        &self,
+        patent_details: &PatentDetails,
+    ) -> Result<OpsResponse, OpsError> {
+        self.run(self.core.get_image_inquiry_response(patent_details)?)
+            .await
+    }
+
+    pub async fn get_image_page(
+        &self,
+        link: &str,
+        page: u32,
+        format: ImageFormat,
+    ) -> Result<OpsResponse, OpsError> {
+        self.execute(self.core.image_page_request(link, page, format)?)
+            .await
+    }
+
+    pub async fn download_full_document(
+        &self,
+        patent_details: &PatentDetails,
+        folder: &Path,
+    ) -> Result<PathBuf, OpsError> {
+        let inquiry = self.get_image_inquiry(patent_details).await?;
+        let full_document = inquiry
+            .full_document()
+            .ok_or_else(|| no_full_document(patent_details))?;
+
+        let mut pages = Vec::new();
+        for page in 1..=full_document.number_of_pages {
+            let page_path = full_document_page_path(folder, patent_details, page);
+            if fs::try_exists(&page_path).await? {
+                pages.push(fs::read(&page_path).await?);
+                continue;
+            }
+            debug!(target: "images", "Getting page {} of {}", page, patent_details);
+            let response = self
+                .get_image_page(&full_document.link, page, ImageFormat::Pdf)
+                .await?;
+            if let Some(page_folder) = page_path.parent() {
+                fs::create_dir_all(page_folder).await?;
+            }
+            fs::write(&page_path, &response.body).await?;
+            pages.push(response.body);
+        }
+
+        let document_path = full_document_path(folder, patent_details);
+        fs::write(&document_path, merge_pdfs(&pages)?).await?;
+        Ok(document_path)
+    }
+
+    pub async fn convert_number(
+        &self,
+        patent_details: &PatentDetails,
+        input_format: NumberFormat,
+        output_format: NumberFormat,
+    ) -> Result<PatentDetails, OpsError> {
+        self.run(
+            self.core
+                .convert_number(patent_details, input_format, output_format)?,
+        )
+        .await
+    }
+
+    pub async fn convert_number_response(
+        &self,
+        patent_details: &PatentDetails,
+        input_format: NumberFormat,
+        output_format: NumberFormat,
+    ) -> Result<OpsResponse, OpsError> {
+        self.run(
+            self.core
+                .convert_number_response(patent_details, input_format, output_format)?,
+        )
+        .await
+    }
+
+    pub async fn get_legal_events(
+        &self,
+        patent_details: &PatentDetails,
+    ) -> Result<Vec<LegalEvent>, OpsError> {
+        self.run(self.core.get_legal_events(patent_details)?).await
+    }
+
+    pub async fn get_legal_events_response(
+        &self,
+        patent_details: &PatentDetails,
+    ) -> Result<OpsResponse, OpsError> {
+        self.run(self.core.get_legal_events_response(patent_details)?)
+            .await
+    }
+
+    pub async fn get_cpc(
+        &self,
+        symbol: &str,
+        depth: CpcDepth,
+        ancestors: bool,
+    ) -> Result<CpcClassification, OpsError> {
+        self.run(self.core.get_cpc(symbol, depth, ancestors)).await
+    }
+
+    pub async fn get_cpc_response(
+        &self,
+        symbol: &str,
+        depth: CpcDepth,
+        ancestors: bool,
+    ) -> Result<OpsResponse, OpsError> {
+        self.run(self.core.get_cpc_response(symbol, depth, ancestors))
+            .await
+    }
+
+    pub async fn search_cpc(&self, keywords: &str) -> Result<Vec<CpcSearchResult>, OpsError> {
+        self.run(self.core.search_cpc(keywords)).await
+    }
+
+    pub async fn search_cpc_response(&self, keywords: &str) -> Result<OpsResponse, OpsError> {
+        self.run(self.core.search_cpc_response(keywords)).await
+    }
+
+    pub async fn get_cpc_media(&self, media_name: &str) -> Result<OpsResponse, OpsError> {
+        self.execute(self.core.cpc_media_request(media_name)).await
+    }
+
+    pub async fn map_classification(
+        &self,
+        symbol: &str,
+        from: ClassificationScheme,
+        to: ClassificationScheme,
+    ) -> Result<Vec<ClassificationMapping>, OpsError> {
+        self.run(self.core.map_classification(symbol, from, to))
+            .await
+    }
+
+    pub async fn map_classification_response(
+        &self,
+        symbol: &str,
+        from: ClassificationScheme,
+        to: ClassificationScheme,
+    ) -> Result<OpsResponse, OpsError> {
+        self.run(self.core.map_classification_response(symbol, from, to))
+            .await
+    }
+
+    pub async fn get_usage_data(&self, from: NaiveDate, to: NaiveDate) -> Result<Usage, OpsError> {
+        self.run(self.core.get_usage_data(from, to)).await
+    }
+
+    pub async fn get_usage_data_response(
+        &self,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<OpsResponse, OpsError> {
+        self.run(self.core.get_usage_data_response(from, to)).await
+    }
+
+    pub async fn get_usage_report(
+        &self,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<UsageReport, OpsError> {
+        self.run(self.core.get_usage_report(from, to)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_send<T: Send>(_: T) {}
+
+    #[test]
+    fn test_futures_are_send() {
+        // Needed to be usable from tokio::spawn - the shared state must never be held across an await
+        let client = AsyncOpsClient::new(
+            EpoOpsCredentials::new("key", "secret"),
+            OpsClientSettings::default(),
+        )
+        .unwrap();
+        let date = NaiveDate::from_ymd_opt(2025, 1, 1).unwrap();
+        assert_send(client.get_usage_data(date, date));
+        assert_send(client.search_register("pa=ibm"));
+        assert_send(client.get_publication_bulk(&[], &[]));
+    }
+}
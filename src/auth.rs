@@ -0,0 +1,112 @@
+use crate::{OpsError, TokenResponse};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Debug)]
+struct AccessToken {
+    value: String,
+    expires_at: Instant,
+}
+
+/// Keeps hold of the current access token and knows when it needs replacing.
+///
+/// Fetching a new token is left to the client - this only does the bookkeeping, so the same
+/// manager works regardless of how the request for a new token is sent.
+#[derive(Debug)]
+pub(crate) struct TokenManager {
+    token: Mutex<Option<AccessToken>>,
+    refresh_margin: Duration,
+}
+
+impl TokenManager {
+    pub(crate) fn new(refresh_margin: Duration) -> TokenManager {
+        TokenManager {
+            token: Mutex::new(None),
+            refresh_margin,
+        }
+    }
+
+    /// The current token, unless it's missing or about to expire.
+    pub(crate) fn valid_token(&self) -> Option<String> {
+        self.valid_token_at(Instant::now())
+    }
+
+    fn valid_token_at(&self, now: Instant) -> Option<String> {
+        let token = self.token.lock().unwrap();
+        token
+            .as_ref()
+            .filter(|t| now + self.refresh_margin < t.expires_at)
+            .map(|t| t.value.clone())
+    }
+
+    /// Stores a freshly issued token, returning its value.
+    ///
+    /// `requested_at` should be taken before the token request was sent, so that the time spent
+    /// waiting for the response only makes us refresh slightly early rather than slightly late.
+    pub(crate) fn store(
+        &self,
+        response: &TokenResponse,
+        requested_at: Instant,
+    ) -> Result<String, OpsError> {
+        // expires_in is in seconds. issued_at is in milliseconds on OPS's clock, which we can't
+        //  compare to ours, so it's not used.
+        let expires_in: u64 = response
+            .expires_in
+            .parse()
+            .map_err(|e| OpsError::Decode(Box::new(e)))?;
+        self.store_at(&response.access_token, expires_in, requested_at);
+        Ok(response.access_token.clone())
+    }
+
+    fn store_at(&self, value: &str, expires_in: u64, requested_at: Instant) {
+        let mut token = self.token.lock().unwrap();
+        *token = Some(AccessToken {
+            value: value.to_string(),
+            expires_at: requested_at + Duration::from_secs(expires_in),
+        });
+    }
+
+    /// Forgets `rejected` after OPS refused it. If the token has already been replaced by another
+    /// caller, the newer one is kept.
+    pub(crate) fn invalidate(&self, rejected: &str) {
+        let mut token = self.token.lock().unwrap();
+        if token.as_ref().is_some_and(|t| t.value == rejected) {
+            *token = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_expiry() {
+        let manager = TokenManager::new(Duration::from_secs(60));
+        let now = Instant::now();
+        assert_eq!(manager.valid_token_at(now), None);
+
+        manager.store_at("abc", 1200, now);
+        assert_eq!(manager.valid_token_at(now), Some("abc".to_string()));
+        assert_eq!(
+            manager.valid_token_at(now + Duration::from_secs(1139)),
+            Some("abc".to_string())
+        );
+        // Within the refresh margin, so treated as expired
+        assert_eq!(
+            manager.valid_token_at(now + Duration::from_secs(1141)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_invalidate_only_rejected_token() {
+        let manager = TokenManager::new(Duration::from_secs(60));
+        let now = Instant::now();
+        manager.store_at("new", 1200, now);
+        manager.invalidate("old");
+        assert_eq!(manager.valid_token_at(now), Some("new".to_string()));
+        manager.invalidate("new");
+        assert_eq!(manager.valid_token_at(now), None);
+    }
+}
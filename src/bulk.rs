@@ -0,0 +1,603 @@
+use crate::exchange::Exchange;
+use crate::ops_client::{
+    ClientCore, OpsRequest, check_publication_or_application, push_constituents,
+    reference_type_path,
+};
+use crate::{
+    OpsError, OpsResponse, PatentDetails, PatentReferenceType, RegisterConstituents, ServiceKind,
+};
+use log::debug;
+use reqwest::StatusCode;
+use reqwest::header::HeaderMap;
+use serde_json::Value;
+use std::collections::VecDeque;
+
+/// The most numbers OPS takes in one bulk request - bigger lists get split into requests of this
+/// size.
+pub const MAX_BULK_NUMBERS: usize = 100;
+
+/// What a bulk retrieval got for each number asked for. Every number ends up in exactly one of
+/// `results` and `failures`.
+#[derive(Debug)]
+pub struct BulkResults<T> {
+    pub results: Vec<(PatentDetails, T)>,
+    pub failures: Vec<BulkFailure>,
+    /// Every response the results were read from, in the order they came in.
+    pub responses: Vec<OpsResponse>,
+}
+
+impl<T> Default for BulkResults<T> {
+    fn default() -> Self {
+        BulkResults {
+            results: Vec::new(),
+            failures: Vec::new(),
+            responses: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct BulkFailure {
+    pub patent_details: PatentDetails,
+    pub error: OpsError,
+}
+
+impl BulkFailure {
+    pub(crate) fn new(patent_details: &PatentDetails, error: OpsError) -> Self {
+        BulkFailure {
+            patent_details: patent_details.clone(),
+            error,
+        }
+    }
+}
+
+/// Whether a failed bulk request is down to (some of) the numbers in it, rather than OPS or the
+/// connection - in which case asking for the numbers one at a time is worth a go.
+pub(crate) fn is_number_specific(error: &OpsError) -> bool {
+    match error {
+        OpsError::OpsFault { status, .. } | OpsError::HttpStatus { status, .. } => {
+            status.is_client_error()
+        }
+        _ => false,
+    }
+}
+
+/// Checks a bulk request fits in one go: at most `MAX_BULK_NUMBERS` numbers, all of the same type
+/// - OPS takes one type per request.
+pub(crate) fn check_bulk_numbers(
+    patents_details: &[PatentDetails],
+) -> Result<&PatentDetails, OpsError> {
+    let first_patent = patents_details.first().ok_or_else(|| {
+        OpsError::InvalidInput("Need at least one patent for a bulk request".to_string())
+    })?;
+    if patents_details.len() > MAX_BULK_NUMBERS {
+        return Err(OpsError::InvalidInput(format!(
+            "At most {} numbers fit in one bulk request, got {}",
+            MAX_BULK_NUMBERS,
+            patents_details.len()
+        )));
+    }
+    for patent_details in patents_details {
+        if patent_details.reference_type != first_patent.reference_type {
+            return Err(OpsError::InvalidInput(format!(
+                "All patents in a bulk request need the same reference type - {} is {:?} but {} is {:?}",
+                patent_details,
+                patent_details.reference_type,
+                first_patent,
+                first_patent.reference_type
+            )));
+        }
+    }
+    Ok(first_patent)
+}
+
+pub(crate) fn bulk_body(patents_details: &[PatentDetails]) -> String {
+    let mut data = String::new();
+    for patent_details in patents_details {
+        data.push_str(&patent_details.to_string());
+        data.push('\n');
+    }
+    data
+}
+
+// The same number can come back with or without its check digit, separators etc.
+fn number_key(country_code: &str, number: &str) -> String {
+    let number = number.split('.').next().unwrap_or_default();
+    format!("{}{}", country_code.trim(), number)
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .collect::<String>()
+        .to_ascii_uppercase()
+}
+
+fn as_slice(value: Option<&Value>) -> &[Value] {
+    match value {
+        Some(Value::Array(values)) => values,
+        Some(value) => std::slice::from_ref(value),
+        None => &[],
+    }
+}
+
+// Every number a register document is known by, as keys
+fn register_document_keys(register_document: &Value) -> Vec<String> {
+    let Some(biblio) = register_document.get("reg:bibliographic-data") else {
+        return Vec::new();
+    };
+    ["reg:application-reference", "reg:publication-reference"]
+        .iter()
+        .flat_map(|reference| as_slice(biblio.get(reference)))
+        .flat_map(|reference| as_slice(reference.get("reg:document-id")))
+        .filter_map(|document_id| {
+            Some(number_key(
+                document_id.pointer("/reg:country/$")?.as_str()?,
+                document_id.pointer("/reg:doc-number/$")?.as_str()?,
+            ))
+        })
+        .collect()
+}
+
+fn not_found(patent_details: PatentDetails, message: String) -> BulkFailure {
+    BulkFailure {
+        patent_details,
+        error: OpsError::OpsFault {
+            status: StatusCode::NOT_FOUND,
+            code: "SERVER.EntityNotFound".to_string(),
+            message,
+        },
+    }
+}
+
+impl BulkResults<Value> {
+    /// Shares the `reg:register-document`s of a (bulk) register response out to the numbers that
+    /// were asked for.
+    pub(crate) fn add_register_response(
+        &mut self,
+        patents_details: &[PatentDetails],
+        response: &OpsResponse,
+    ) -> Result<(), OpsError> {
+        let json: Value = response.json()?;
+        let register_documents: Vec<(Vec<String>, &Value)> = as_slice(json.pointer(
+            "/ops:world-patent-data/ops:register-search/reg:register-documents/reg:register-document",
+        ))
+        .iter()
+        .map(|register_document| (register_document_keys(register_document), register_document))
+        .collect();
+        for patent_details in patents_details {
+            let key = number_key(&patent_details.country_code, &patent_details.number);
+            match register_documents
+                .iter()
+                .find(|(keys, _)| keys.contains(&key))
+            {
+                Some((_, register_document)) => self
+                    .results
+                    .push((patent_details.clone(), (*register_document).clone())),
+                None => {
+                    debug!(target: "register", "{} missing from the bulk response", patent_details);
+                    let message = format!(
+                        "No register document for {} in the response",
+                        patent_details
+                    );
+                    self.failures
+                        .push(not_found(patent_details.clone(), message));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Every number an exchange-document is known by for the type of number that was asked for, as
+//  keys
+fn exchange_document_keys(
+    exchange_document: &Value,
+    reference_type: &PatentReferenceType,
+) -> Vec<String> {
+    let biblio = exchange_document.get("bibliographic-data");
+    let references: Vec<&Value> = match reference_type {
+        PatentReferenceType::Application => {
+            as_slice(biblio.and_then(|b| b.get("application-reference")))
+                .iter()
+                .collect()
+        }
+        PatentReferenceType::Priority => as_slice(
+            biblio
+                .and_then(|b| b.get("priority-claims"))
+                .and_then(|p| p.get("priority-claim")),
+        )
+        .iter()
+        .collect(),
+        _ => as_slice(biblio.and_then(|b| b.get("publication-reference")))
+            .iter()
+            .collect(),
+    };
+    let mut keys: Vec<String> = references
+        .into_iter()
+        .flat_map(|reference| as_slice(reference.get("document-id")))
+        .filter_map(|document_id| {
+            // epodoc numbers have the country on the front already
+            let country = document_id
+                .pointer("/country/$")
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+            Some(number_key(
+                country,
+                document_id.pointer("/doc-number/$")?.as_str()?,
+            ))
+        })
+        .collect();
+    // The attributes are the publication number - or, for numbers OPS doesn't have, whatever was
+    //  asked for
+    let attributes_apply = *reference_type == PatentReferenceType::Publication
+        || exchange_document.get("@status").is_some();
+    if let (true, Some(country), Some(number)) = (
+        attributes_apply,
+        exchange_document.get("@country").and_then(Value::as_str),
+        exchange_document.get("@doc-number").and_then(Value::as_str),
+    ) {
+        keys.push(number_key(country, number));
+    }
+    keys
+}
+
+fn kind_matches(patent_details: &PatentDetails, exchange_document: &Value) -> bool {
+    match (
+        &patent_details.kind_code,
+        exchange_document.get("@kind").and_then(Value::as_str),
+    ) {
+        (Some(asked_for), Some(kind))
+            if patent_details.reference_type == PatentReferenceType::Publication =>
+        {
+            asked_for.eq_ignore_ascii_case(kind)
+        }
+        _ => true,
+    }
+}
+
+impl BulkResults<Vec<Value>> {
+    /// Shares the `exchange-document`s of a (bulk) published-data response out to the numbers
+    /// that were asked for - a number can have several, e.g. its A1 and B1.
+    ///
+    /// OPS reports numbers it couldn't find as an `exchange-document` with a `@status` (e.g. "not
+    /// found"), which end up as failures.
+    pub(crate) fn add_publication_response(
+        &mut self,
+        patents_details: &[PatentDetails],
+        response: &OpsResponse,
+    ) -> Result<(), OpsError> {
+        let json: Value = response.json()?;
+        let exchange_documents: Vec<&Value> =
+            as_slice(json.pointer("/ops:world-patent-data/exchange-documents"))
+                .iter()
+                .flat_map(|documents| as_slice(documents.get("exchange-document")))
+                .collect();
+        for patent_details in patents_details {
+            let key = number_key(&patent_details.country_code, &patent_details.number);
+            let (found, statuses): (Vec<&Value>, Vec<&Value>) = exchange_documents
+                .iter()
+                .filter(|document| {
+                    exchange_document_keys(document, &patent_details.reference_type).contains(&key)
+                        && kind_matches(patent_details, document)
+                })
+                .partition(|document| document.get("@status").is_none());
+            if !found.is_empty() {
+                self.results
+                    .push((patent_details.clone(), found.into_iter().cloned().collect()));
+                continue;
+            }
+            debug!(target: "bulk", "{} missing from the bulk response", patent_details);
+            let status = statuses
+                .first()
+                .and_then(|document| document.get("@status"))
+                .and_then(Value::as_str)
+                .unwrap_or("not in the response");
+            let message = format!("{}: {}", patent_details, status);
+            self.failures
+                .push(not_found(patent_details.clone(), message));
+        }
+        Ok(())
+    }
+}
+
+impl ClientCore {
+    pub(crate) fn register_bulk_request(
+        &self,
+        patents_details: &[PatentDetails],
+        constituents: &[RegisterConstituents],
+    ) -> Result<OpsRequest, OpsError> {
+        let first_patent = check_bulk_numbers(patents_details)?;
+        let mut url = reference_type_path(
+            first_patent,
+            &self.settings.endpoints.service_url("register"),
+        )?;
+        push_constituents(&mut url, constituents, RegisterConstituents::Biblio);
+        let mut headers = HeaderMap::new();
+        headers.insert("Content-Type", "text/plain".parse().unwrap());
+        headers.insert("Accept", self.accept());
+        Ok(OpsRequest::post(
+            &url,
+            Some(bulk_body(patents_details)),
+            headers,
+            ServiceKind::Retrieval,
+        ))
+    }
+
+    pub(crate) fn get_register_info_bulk<'a>(
+        &'a self,
+        patents_details: &[PatentDetails],
+        constituents: &'a [RegisterConstituents],
+    ) -> Bulk<'a, Value> {
+        Bulk::new(
+            patents_details,
+            check_publication_or_application,
+            move |chunk| self.register_bulk_request(chunk, constituents),
+            BulkResults::add_register_response,
+        )
+    }
+}
+
+type BulkRequest<'a> = Box<dyn Fn(&[PatentDetails]) -> Result<OpsRequest, OpsError> + Send + 'a>;
+type AddResponse<T> =
+    fn(&mut BulkResults<T>, &[PatentDetails], &OpsResponse) -> Result<(), OpsError>;
+
+/// Asks for the numbers in bulk requests of up to `MAX_BULK_NUMBERS`, sharing each response out
+/// with `add`. Numbers `check` turns down (e.g. of a type the service doesn't take) go straight to
+/// the failures without being sent. When a request fails because of (some of) its numbers,
+/// they're asked for one at a time so only the bad ones end up in the failures; any other error
+/// stops the whole lot.
+pub(crate) struct Bulk<'a, T> {
+    request: BulkRequest<'a>,
+    add: AddResponse<T>,
+    chunks: VecDeque<Vec<PatentDetails>>,
+    // The numbers in the request that was sent last
+    sent: Vec<PatentDetails>,
+    results: BulkResults<T>,
+}
+
+impl<'a, T> Bulk<'a, T> {
+    pub(crate) fn new(
+        patents_details: &[PatentDetails],
+        check: fn(&PatentDetails) -> Result<(), OpsError>,
+        request: impl Fn(&[PatentDetails]) -> Result<OpsRequest, OpsError> + Send + 'a,
+        add: AddResponse<T>,
+    ) -> Self {
+        let mut results = BulkResults::default();
+        let mut supported = Vec::new();
+        for patent_details in patents_details {
+            match check(patent_details) {
+                Ok(()) => supported.push(patent_details.clone()),
+                Err(e) => results.failures.push(BulkFailure::new(patent_details, e)),
+            }
+        }
+        Bulk {
+            request: Box::new(request),
+            add,
+            chunks: bulk_chunks(&supported).into(),
+            sent: Vec::new(),
+            results,
+        }
+    }
+}
+
+impl<T> Exchange for Bulk<'_, T> {
+    type Output = BulkResults<T>;
+
+    fn next_request(&mut self) -> Result<Option<OpsRequest>, OpsError> {
+        let Some(chunk) = self.chunks.pop_front() else {
+            return Ok(None);
+        };
+        let request = (self.request)(&chunk)?;
+        self.sent = chunk;
+        Ok(Some(request))
+    }
+
+    fn handle(&mut self, response: Result<OpsResponse, OpsError>) -> Result<(), OpsError> {
+        match response {
+            Ok(response) => {
+                (self.add)(&mut self.results, &self.sent, &response)?;
+                self.results.responses.push(response);
+            }
+            Err(e) if is_number_specific(&e) && self.sent.len() == 1 => {
+                self.results
+                    .failures
+                    .push(BulkFailure::new(&self.sent[0], e));
+            }
+            // One bad number fails the whole request - asking one at a time finds out which
+            Err(e) if is_number_specific(&e) => {
+                debug!(
+                    target: "bulk",
+                    "Bulk request failed ({}), asking for the {} numbers one at a time",
+                    e,
+                    self.sent.len()
+                );
+                for patent_details in self.sent.drain(..).rev() {
+                    self.chunks.push_front(vec![patent_details]);
+                }
+            }
+            Err(e) => return Err(e),
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<BulkResults<T>, OpsError> {
+        Ok(self.results)
+    }
+}
+
+/// Splits the numbers into bulk requests of at most `MAX_BULK_NUMBERS` that share a reference
+/// type. The numbers are grouped by type first, so only the last request of each type can be
+/// short.
+pub(crate) fn bulk_chunks(patents_details: &[PatentDetails]) -> Vec<Vec<PatentDetails>> {
+    let mut by_type: Vec<Vec<PatentDetails>> = Vec::new();
+    for patent_details in patents_details {
+        match by_type
+            .iter_mut()
+            .find(|group| group[0].reference_type == patent_details.reference_type)
+        {
+            Some(group) => group.push(patent_details.clone()),
+            None => by_type.push(vec![patent_details.clone()]),
+        }
+    }
+    by_type
+        .iter()
+        .flat_map(|group| group.chunks(MAX_BULK_NUMBERS))
+        .map(<[PatentDetails]>::to_vec)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::response::fixture_response;
+
+    fn application(number: &str) -> PatentDetails {
+        PatentDetails {
+            country_code: "EP".to_string(),
+            number: number.to_string(),
+            kind_code: None,
+            date: None,
+            reference_type: PatentReferenceType::Application,
+        }
+    }
+
+    #[test]
+    fn test_register_bulk_matching() {
+        let response = fixture_response("test/example_register.json");
+        let mut results = BulkResults::default();
+        results
+            .add_register_response(
+                &[application("13860559.4"), application("99203729")],
+                &response,
+            )
+            .unwrap();
+        assert_eq!(results.results.len(), 1);
+        assert_eq!(results.results[0].0.number, "13860559.4");
+        assert!(results.results[0].1.get("reg:bibliographic-data").is_some());
+        assert_eq!(results.failures.len(), 1);
+        assert_eq!(results.failures[0].patent_details.number, "99203729");
+        assert!(is_number_specific(&results.failures[0].error));
+    }
+
+    #[test]
+    fn test_publication_bulk_matching() {
+        let response = fixture_response("test/example_publication_bulk.json");
+        let publication = |country: &str, number: &str, kind: Option<&str>| PatentDetails {
+            country_code: country.to_string(),
+            number: number.to_string(),
+            kind_code: kind.map(str::to_string),
+            date: None,
+            reference_type: PatentReferenceType::Publication,
+        };
+        let mut results = BulkResults::default();
+        results
+            .add_publication_response(
+                &[
+                    publication("EP", "1000000", None),
+                    publication("US", "6093011", Some("A")),
+                    publication("EP", "9999999", None),
+                    publication("EP", "1234567", None),
+                ],
+                &response,
+            )
+            .unwrap();
+        assert_eq!(results.results.len(), 2);
+        assert_eq!(results.results[0].1.len(), 2);
+        assert_eq!(results.results[1].1.len(), 1);
+        let failed: Vec<String> = results
+            .failures
+            .iter()
+            .map(|f| f.patent_details.to_string())
+            .collect();
+        assert_eq!(failed, vec!["EP9999999", "EP1234567"]);
+        assert!(results.failures[0].error.to_string().contains("not found"));
+
+        // Both stages came from the same application
+        let mut results = BulkResults::default();
+        results
+            .add_publication_response(&[application("99203729")], &response)
+            .unwrap();
+        assert_eq!(results.results[0].1.len(), 2);
+
+        let too_many: Vec<PatentDetails> = (0..=MAX_BULK_NUMBERS)
+            .map(|i| application(&format!("{}", 1000000 + i)))
+            .collect();
+        assert!(check_bulk_numbers(&too_many).is_err());
+    }
+
+    #[test]
+    fn test_bulk_one_at_a_time() {
+        let core = crate::ops_client::test_core();
+        let patents_details: Vec<PatentDetails> = ["13860559.4", "99203729"]
+            .into_iter()
+            .map(application)
+            .collect();
+        let mut bulk = core.get_register_info_bulk(&patents_details, &[]);
+        let not_found = || OpsError::OpsFault {
+            status: StatusCode::NOT_FOUND,
+            code: "SERVER.EntityNotFound".to_string(),
+            message: "No results found".to_string(),
+        };
+        let mut sent = Vec::new();
+        while let Some(request) = bulk.next_request().unwrap() {
+            let body = request.body.unwrap();
+            let response = match body.trim() {
+                "EP13860559.4" => Ok(fixture_response("test/example_register.json")),
+                _ => Err(not_found()),
+            };
+            sent.push(body);
+            bulk.handle(response).unwrap();
+        }
+        assert_eq!(sent.len(), 3);
+        let results = bulk.finish().unwrap();
+        assert_eq!(results.responses.len(), 1);
+        assert_eq!(results.results[0].0.number, "13860559.4");
+        assert_eq!(results.failures[0].patent_details.number, "99203729");
+    }
+
+    #[test]
+    fn test_bulk_unsupported_numbers() {
+        let core = crate::ops_client::test_core();
+        let mut unknown = application("1000001");
+        unknown.reference_type = PatentReferenceType::Unknown;
+        let patents_details = vec![application("99203729"), unknown];
+        let mut bulk = core.get_publication_bulk(&patents_details, &[]);
+        let request = bulk.next_request().unwrap().unwrap();
+        assert_eq!(request.body.as_deref(), Some("EP99203729\n"));
+        bulk.handle(Ok(fixture_response("test/example_publication_bulk.json")))
+            .unwrap();
+        assert!(bulk.next_request().unwrap().is_none());
+        let results = bulk.finish().unwrap();
+        assert_eq!(results.results[0].0.number, "99203729");
+        assert_eq!(results.failures[0].patent_details.number, "1000001");
+        assert!(matches!(
+            results.failures[0].error,
+            OpsError::InvalidInput(_)
+        ));
+
+        // The register doesn't take priority numbers
+        let mut priority = application("1000002");
+        priority.reference_type = PatentReferenceType::Priority;
+        let mut bulk = core.get_register_info_bulk(std::slice::from_ref(&priority), &[]);
+        assert!(bulk.next_request().unwrap().is_none());
+        let results = bulk.finish().unwrap();
+        assert_eq!(results.failures[0].patent_details.number, "1000002");
+    }
+
+    #[test]
+    fn test_bulk_chunks() {
+        let mut patents_details: Vec<PatentDetails> = (0..250)
+            .map(|i| application(&format!("{}", 1000000 + i)))
+            .collect();
+        let sizes: Vec<usize> = bulk_chunks(&patents_details)
+            .iter()
+            .map(|c| c.len())
+            .collect();
+        assert_eq!(sizes, vec![100, 100, 50]);
+
+        patents_details[10].reference_type = PatentReferenceType::Publication;
+        let sizes: Vec<usize> = bulk_chunks(&patents_details)
+            .iter()
+            .map(|c| c.len())
+            .collect();
+        assert_eq!(sizes, vec![100, 100, 49, 1]);
+    }
+}
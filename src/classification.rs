@@ -0,0 +1,498 @@
+use crate::deser::{OneOrMany, text_content};
+use crate::exchange::{Exchange, Single};
+use crate::ops_client::{ClientCore, OpsRequest, encode_input};
+use crate::{OpsError, OpsResponse, ServiceKind};
+use reqwest::header::HeaderMap;
+use serde::Deserialize;
+use serde_json::Value;
+use std::fmt;
+
+/// How far below the requested symbol to go.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CpcDepth {
+    Levels(u32),
+    All,
+}
+
+impl fmt::Display for CpcDepth {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CpcDepth::Levels(levels) => write!(f, "{}", levels),
+            CpcDepth::All => write!(f, "all"),
+        }
+    }
+}
+
+/// One entry of the CPC scheme, with however much of the tree below it was asked for.
+#[derive(Debug)]
+pub struct CpcItem {
+    /// e.g. "A01B1/00".
+    pub symbol: String,
+    pub level: Option<u32>,
+    pub title: String,
+    pub children: Vec<CpcItem>,
+}
+
+/// A CPC symbol retrieved from the classification service - section 3.7 of the EPO OPS doc.
+#[derive(Debug)]
+pub struct CpcClassification {
+    /// From the top of the scheme down to (not including) `item`, without their other children.
+    pub ancestors: Vec<CpcItem>,
+    pub item: CpcItem,
+}
+
+impl CpcClassification {
+    /// The symbol one level up, e.g. to move up the hierarchy with another request.
+    pub fn parent_symbol(&self) -> Option<&str> {
+        self.ancestors.last().map(|a| a.symbol.as_str())
+    }
+}
+
+#[derive(Debug)]
+pub struct CpcSearchResult {
+    pub symbol: String,
+    /// How relevant OPS considers the symbol to the keywords, 0 - 100.
+    pub percentage: Option<f64>,
+    pub title: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClassificationScheme {
+    Cpc,
+    Ipc,
+    Ecla,
+}
+
+impl fmt::Display for ClassificationScheme {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ClassificationScheme::Cpc => write!(f, "cpc"),
+            ClassificationScheme::Ipc => write!(f, "ipc"),
+            ClassificationScheme::Ecla => write!(f, "ecla"),
+        }
+    }
+}
+
+/// One correspondence between the schemes. Only the schemes involved in the mapping are filled.
+#[derive(Debug, Default)]
+pub struct ClassificationMapping {
+    pub cpc: Option<String>,
+    pub ipc: Option<String>,
+    pub ecla: Option<String>,
+    /// Whether the symbol is only used for additional information.
+    pub additional_only: bool,
+}
+
+/* Classification structs
+ *  These are for deserialising the JSON from the CPC retrieval, search and mapping services.
+ *  The cpc: prefix depends on the namespace handling of the moment, so both are accepted.
+ */
+
+#[derive(Debug, Deserialize)]
+struct CpcClassificationItem {
+    #[serde(rename(deserialize = "@level"))]
+    level: Option<String>,
+    #[serde(
+        rename(deserialize = "cpc:classification-symbol"),
+        alias = "classification-symbol"
+    )]
+    classification_symbol: Value,
+    #[serde(rename(deserialize = "cpc:class-title"), alias = "class-title")]
+    class_title: Option<Value>,
+    #[serde(
+        rename(deserialize = "cpc:classification-item"),
+        alias = "classification-item",
+        default
+    )]
+    // Boxed as the items nest
+    classification_item: OneOrMany<Box<CpcClassificationItem>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CpcClassScheme {
+    #[serde(
+        rename(deserialize = "cpc:classification-item"),
+        alias = "classification-item",
+        default
+    )]
+    classification_item: OneOrMany<CpcClassificationItem>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CpcWorldPatentData {
+    #[serde(rename(deserialize = "cpc:class-scheme"), alias = "class-scheme")]
+    class_scheme: CpcClassScheme,
+}
+
+#[derive(Debug, Deserialize)]
+struct CpcResult {
+    #[serde(rename(deserialize = "ops:world-patent-data"))]
+    ops_world_patent_data: CpcWorldPatentData,
+}
+
+#[derive(Debug, Deserialize)]
+struct CpcClassificationStatistics {
+    #[serde(rename(deserialize = "@classification-symbol"))]
+    classification_symbol: String,
+    #[serde(rename(deserialize = "@percentage"))]
+    percentage: Option<String>,
+    #[serde(rename(deserialize = "ops:class-title"), alias = "class-title")]
+    class_title: Option<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CpcSearchSearchResult {
+    #[serde(rename(deserialize = "ops:classification-statistics"), default)]
+    ops_classification_statistics: OneOrMany<CpcClassificationStatistics>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CpcClassificationSearch {
+    #[serde(rename(deserialize = "ops:search-result"))]
+    ops_search_result: Option<CpcSearchSearchResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CpcSearchWorldPatentData {
+    #[serde(rename(deserialize = "ops:classification-search"))]
+    ops_classification_search: CpcClassificationSearch,
+}
+
+#[derive(Debug, Deserialize)]
+struct CpcSearch {
+    #[serde(rename(deserialize = "ops:world-patent-data"))]
+    ops_world_patent_data: CpcSearchWorldPatentData,
+}
+
+#[derive(Debug, Deserialize)]
+struct MapMappings {
+    #[serde(rename(deserialize = "ops:mapping"), default)]
+    ops_mapping: OneOrMany<serde_json::Map<String, Value>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MapWorldPatentData {
+    #[serde(rename(deserialize = "ops:mappings"))]
+    ops_mappings: MapMappings,
+}
+
+#[derive(Debug, Deserialize)]
+struct MapResult {
+    #[serde(rename(deserialize = "ops:world-patent-data"))]
+    ops_world_patent_data: MapWorldPatentData,
+}
+
+// Symbols get written with and without the space, e.g. "A01B 1/00"
+fn normalise_symbol(symbol: &str) -> String {
+    symbol.split_whitespace().collect()
+}
+
+// Titles come in parts (e.g. "SOIL WORKING" and "PARTS OF PLOUGHS"), joined up like on the website
+fn title_text(class_title: &Option<Value>) -> String {
+    let Some(class_title) = class_title else {
+        return String::new();
+    };
+    let title_parts = class_title
+        .get("cpc:title-part")
+        .or_else(|| class_title.get("title-part"))
+        .or_else(|| class_title.get("ops:title-part"));
+    let parts: Vec<String> = match title_parts {
+        Some(Value::Array(parts)) => parts.iter().map(title_part_text).collect(),
+        Some(part) => vec![title_part_text(part)],
+        None => vec![text_content(class_title)],
+    };
+    parts
+        .iter()
+        .map(|p| p.trim())
+        .filter(|p| !p.is_empty())
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+fn title_part_text(title_part: &Value) -> String {
+    // Only the text, not the references to other symbols
+    match title_part
+        .get("cpc:text")
+        .or_else(|| title_part.get("text"))
+        .or_else(|| title_part.get("ops:text"))
+    {
+        Some(text) => text_content(text),
+        None => text_content(title_part),
+    }
+}
+
+impl From<CpcClassificationItem> for CpcItem {
+    fn from(item: CpcClassificationItem) -> Self {
+        CpcItem {
+            symbol: normalise_symbol(&text_content(&item.classification_symbol)),
+            level: item.level.and_then(|l| l.trim().parse().ok()),
+            title: title_text(&item.class_title),
+            children: item
+                .classification_item
+                .into_vec()
+                .into_iter()
+                .map(|child| CpcItem::from(*child))
+                .collect(),
+        }
+    }
+}
+
+// Walks down the tree to `symbol`, collecting everything on the way as the ancestors
+fn find_item(items: Vec<CpcItem>, symbol: &str, ancestors: &mut Vec<CpcItem>) -> Option<CpcItem> {
+    for mut item in items {
+        if item.symbol == symbol {
+            return Some(item);
+        }
+        let children = std::mem::take(&mut item.children);
+        ancestors.push(item);
+        if let Some(found) = find_item(children, symbol, ancestors) {
+            return Some(found);
+        }
+        ancestors.pop();
+    }
+    None
+}
+
+pub(crate) fn parse_cpc(
+    response: &OpsResponse,
+    symbol: &str,
+) -> Result<CpcClassification, OpsError> {
+    let result: CpcResult = response.json()?;
+    let items: Vec<CpcItem> = result
+        .ops_world_patent_data
+        .class_scheme
+        .classification_item
+        .into_vec()
+        .into_iter()
+        .map(CpcItem::from)
+        .collect();
+    let mut ancestors = Vec::new();
+    let item = find_item(items, &normalise_symbol(symbol), &mut ancestors).ok_or_else(|| {
+        OpsError::Decode(format!("{} isn't in the classification scheme returned", symbol).into())
+    })?;
+    Ok(CpcClassification { ancestors, item })
+}
+
+pub(crate) fn parse_cpc_search(response: &OpsResponse) -> Result<Vec<CpcSearchResult>, OpsError> {
+    let result: CpcSearch = response.json()?;
+    let Some(search_result) = result
+        .ops_world_patent_data
+        .ops_classification_search
+        .ops_search_result
+    else {
+        return Ok(Vec::new());
+    };
+    Ok(search_result
+        .ops_classification_statistics
+        .into_vec()
+        .into_iter()
+        .map(|statistics| CpcSearchResult {
+            symbol: normalise_symbol(&statistics.classification_symbol),
+            percentage: statistics.percentage.and_then(|p| p.trim().parse().ok()),
+            title: title_text(&statistics.class_title),
+        })
+        .collect())
+}
+
+pub(crate) fn parse_classification_mapping(
+    response: &OpsResponse,
+) -> Result<Vec<ClassificationMapping>, OpsError> {
+    let result: MapResult = response.json()?;
+    Ok(result
+        .ops_world_patent_data
+        .ops_mappings
+        .ops_mapping
+        .into_vec()
+        .into_iter()
+        .map(|fields| {
+            let mut mapping = ClassificationMapping::default();
+            for (name, value) in fields {
+                let text = normalise_symbol(&text_content(&value));
+                match name.strip_prefix("ops:").unwrap_or(&name) {
+                    "cpc" => mapping.cpc = Some(text),
+                    "ipc" => mapping.ipc = Some(text),
+                    "ecla" => mapping.ecla = Some(text),
+                    "additional-only" => mapping.additional_only = text == "true",
+                    _ => {}
+                }
+            }
+            mapping
+        })
+        .collect())
+}
+
+impl ClientCore {
+    fn classification_headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("Accept", self.accept());
+        headers
+    }
+
+    fn cpc_url(&self) -> String {
+        self.settings.endpoints.service_url("classification/cpc")
+    }
+
+    pub(crate) fn cpc_request(&self, symbol: &str, depth: CpcDepth, ancestors: bool) -> OpsRequest {
+        let mut url = format!(
+            "{}/{}?depth={}",
+            self.cpc_url(),
+            normalise_symbol(symbol),
+            depth
+        );
+        if ancestors {
+            url.push_str("&ancestors");
+        }
+        OpsRequest::get(&url, self.classification_headers(), ServiceKind::Other)
+    }
+
+    pub(crate) fn cpc_search_request(&self, keywords: &str) -> OpsRequest {
+        let url = format!("{}/search/?q={}", self.cpc_url(), encode_input(keywords));
+        OpsRequest::get(&url, self.classification_headers(), ServiceKind::Other)
+    }
+
+    /// The request for an illustration referred to from the scheme, e.g. "1000.gif".
+    pub(crate) fn cpc_media_request(&self, media_name: &str) -> OpsRequest {
+        let url = format!("{}/media/{}", self.cpc_url(), media_name);
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "Accept",
+            "image/gif, image/png, image/jpeg".parse().unwrap(),
+        );
+        OpsRequest::get(&url, headers, ServiceKind::Other)
+    }
+
+    pub(crate) fn classification_mapping_request(
+        &self,
+        symbol: &str,
+        from: ClassificationScheme,
+        to: ClassificationScheme,
+    ) -> OpsRequest {
+        let url = format!(
+            "{}/{}/{}/{}",
+            self.settings.endpoints.service_url("classification/map"),
+            from,
+            normalise_symbol(symbol),
+            to
+        );
+        OpsRequest::get(&url, self.classification_headers(), ServiceKind::Other)
+    }
+
+    pub(crate) fn get_cpc_response(
+        &self,
+        symbol: &str,
+        depth: CpcDepth,
+        ancestors: bool,
+    ) -> Single {
+        Single::new(self.cpc_request(symbol, depth, ancestors))
+    }
+
+    pub(crate) fn get_cpc(
+        &self,
+        symbol: &str,
+        depth: CpcDepth,
+        ancestors: bool,
+    ) -> impl Exchange<Output = CpcClassification> + use<> {
+        let symbol = symbol.to_string();
+        self.get_cpc_response(&symbol, depth, ancestors)
+            .map(move |response| parse_cpc(&response, &symbol))
+    }
+
+    pub(crate) fn search_cpc_response(&self, keywords: &str) -> Single {
+        Single::new(self.cpc_search_request(keywords))
+    }
+
+    pub(crate) fn search_cpc(
+        &self,
+        keywords: &str,
+    ) -> impl Exchange<Output = Vec<CpcSearchResult>> {
+        self.search_cpc_response(keywords)
+            .map(|response| parse_cpc_search(&response))
+    }
+
+    pub(crate) fn map_classification_response(
+        &self,
+        symbol: &str,
+        from: ClassificationScheme,
+        to: ClassificationScheme,
+    ) -> Single {
+        Single::new(self.classification_mapping_request(symbol, from, to))
+    }
+
+    pub(crate) fn map_classification(
+        &self,
+        symbol: &str,
+        from: ClassificationScheme,
+        to: ClassificationScheme,
+    ) -> impl Exchange<Output = Vec<ClassificationMapping>> {
+        self.map_classification_response(symbol, from, to)
+            .map(|response| parse_classification_mapping(&response))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ops_client::test_core;
+    use crate::response::fixture_response;
+
+    #[test]
+    fn test_cpc_deser() {
+        let classification =
+            parse_cpc(&fixture_response("test/example_cpc.json"), "A01B 1/00").unwrap();
+        let ancestors: Vec<&str> = classification
+            .ancestors
+            .iter()
+            .map(|a| a.symbol.as_str())
+            .collect();
+        assert_eq!(ancestors, vec!["A", "A01", "A01B"]);
+        assert_eq!(classification.parent_symbol(), Some("A01B"));
+        assert!(classification.ancestors[2].children.is_empty());
+        assert_eq!(classification.ancestors[0].title, "HUMAN NECESSITIES");
+
+        let item = &classification.item;
+        assert_eq!(item.symbol, "A01B1/00");
+        assert_eq!(item.level, Some(7));
+        assert_eq!(item.title, "Hand tools");
+        assert_eq!(item.children.len(), 2);
+        assert_eq!(item.children[1].title, "Spades; Shovels; with teeth");
+
+        let results = parse_cpc_search(&fixture_response("test/example_cpc_search.json")).unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].symbol, "A01B1/02");
+        assert_eq!(results[0].percentage, Some(56.2));
+
+        let mappings =
+            parse_classification_mapping(&fixture_response("test/example_classification_map.json"))
+                .unwrap();
+        assert_eq!(mappings.len(), 1);
+        assert_eq!(mappings[0].cpc.as_deref(), Some("A61K9/0002"));
+        assert_eq!(mappings[0].ecla.as_deref(), Some("A61K9/00A"));
+        assert!(!mappings[0].additional_only);
+    }
+
+    #[test]
+    fn test_classification_requests() {
+        let core = test_core();
+        assert_eq!(
+            core.cpc_request("A01B 1/00", CpcDepth::Levels(1), true).url,
+            "https://ops.epo.org/3.2/rest-services/classification/cpc/A01B1/00?depth=1&ancestors"
+        );
+        assert_eq!(
+            core.cpc_request("A01B", CpcDepth::All, false).url,
+            "https://ops.epo.org/3.2/rest-services/classification/cpc/A01B?depth=all"
+        );
+        assert_eq!(
+            core.cpc_search_request("hand tools").url,
+            "https://ops.epo.org/3.2/rest-services/classification/cpc/search/?q=hand%20tools"
+        );
+        assert_eq!(
+            core.classification_mapping_request(
+                "A61K9/00A",
+                ClassificationScheme::Ecla,
+                ClassificationScheme::Cpc
+            )
+            .url,
+            "https://ops.epo.org/3.2/rest-services/classification/map/ecla/A61K9/00A/cpc"
+        );
+    }
+}
@@ -1,12 +1,12 @@
+use crate::{OpsClientSettings, ProxySettings, QuotaGuard, ResponseFormat};
 use base64::prelude::*;
 use ini::Ini;
-use once_cell::sync::OnceCell;
+use std::time::Duration;
 
-static CONFIG: OnceCell<Config> = OnceCell::new();
-
-struct Config {
+pub struct Config {
     epo_credentials: EpoOpsCredentials,
     cache_downloads: String,
+    ops_client_settings: OpsClientSettings,
 }
 
 #[derive(Clone)]
@@ -16,12 +16,19 @@ pub struct EpoOpsCredentials {
 }
 
 impl EpoOpsCredentials {
+    pub fn new(consumer_key: &str, secret_key: &str) -> EpoOpsCredentials {
+        EpoOpsCredentials {
+            consumer_key: consumer_key.to_string(),
+            secret_key: secret_key.to_string(),
+        }
+    }
+
     pub fn format_credentials(&self) -> String {
         BASE64_STANDARD.encode(format!("{}:{}", self.consumer_key, self.secret_key))
     }
 }
 
-pub fn load_config(file: &str) {
+pub fn load_config(file: &str) -> Config {
     let conf = Ini::load_from_file(file).expect("Couldn't open config file");
     let epo_credentials_conf = conf.section(Some("EPO OPS")).unwrap();
     let epo_credentials = EpoOpsCredentials {
@@ -39,22 +46,87 @@ pub fn load_config(file: &str) {
         .get("cache_downloads")
         .expect("Error finding epo_downloads")
         .to_string();
-    let _ = CONFIG.set(Config {
+    let mut ops_client_settings = OpsClientSettings::default();
+    if let Some(endpoints) = conf.section(Some("Endpoints")) {
+        if let Some(base_url) = endpoints.get("base_url") {
+            ops_client_settings.endpoints.base_url = base_url.to_string();
+        }
+        if let Some(api_version) = endpoints.get("api_version") {
+            ops_client_settings.endpoints.api_version = api_version.to_string();
+        }
+        if let Some(allow_insecure_http) = endpoints.get("allow_insecure_http") {
+            ops_client_settings.endpoints.allow_insecure_http = allow_insecure_http
+                .parse()
+                .expect("allow_insecure_http should be true or false");
+        }
+    }
+    if let Some(http) = conf.section(Some("HTTP")) {
+        let http_settings = &mut ops_client_settings.http;
+        if let Some(connect_timeout) = http.get("connect_timeout_secs") {
+            http_settings.connect_timeout =
+                Some(Duration::from_secs(connect_timeout.parse().expect(
+                    "connect_timeout_secs should be a number of seconds",
+                )));
+        }
+        if let Some(read_timeout) = http.get("read_timeout_secs") {
+            http_settings.read_timeout = Some(Duration::from_secs(
+                read_timeout
+                    .parse()
+                    .expect("read_timeout_secs should be a number of seconds"),
+            ));
+        }
+        if let Some(proxy_url) = http.get("proxy_url") {
+            http_settings.proxy = Some(ProxySettings {
+                url: proxy_url.to_string(),
+                username: http.get("proxy_username").map(str::to_string),
+                password: http.get("proxy_password").map(str::to_string),
+            });
+        }
+        if let Some(user_agent) = http.get("user_agent") {
+            http_settings.user_agent = user_agent.to_string();
+        }
+        if let Some(gzip) = http.get("gzip") {
+            http_settings.gzip = gzip.parse().expect("gzip should be true or false");
+        }
+        if let Some(response_format) = http.get("response_format") {
+            ops_client_settings.response_format = match response_format {
+                "json" => ResponseFormat::Json,
+                "xml" => ResponseFormat::Xml,
+                _ => panic!("response_format should be json or xml"),
+            };
+        }
+    }
+    if let Some(quota) = conf.section(Some("Quota")) {
+        let mut quota_guard = QuotaGuard::default();
+        if let Some(weekly_quota) = quota.get("weekly_quota") {
+            quota_guard.weekly_quota = weekly_quota
+                .parse()
+                .expect("weekly_quota should be a number of bytes");
+        }
+        if let Some(max_weekly_share) = quota.get("max_weekly_share") {
+            quota_guard.max_weekly_share = max_weekly_share
+                .parse()
+                .expect("max_weekly_share should be a number between 0 and 1");
+        }
+        ops_client_settings.quota_guard = Some(quota_guard);
+    }
+    Config {
         epo_credentials,
         cache_downloads,
-    });
+        ops_client_settings,
+    }
 }
 
-pub fn get_epo_credentials() -> EpoOpsCredentials {
-    let config = CONFIG
-        .get()
-        .expect("Config not initialized - you need to call \"load_config\" first");
-    config.epo_credentials.clone()
-}
+impl Config {
+    pub fn epo_credentials(&self) -> EpoOpsCredentials {
+        self.epo_credentials.clone()
+    }
 
-pub fn get_cache_folder() -> String {
-    let config = CONFIG
-        .get()
-        .expect("Config not initialized - you need to call \"load_config\" first");
-    config.cache_downloads.clone()
+    pub fn cache_folder(&self) -> String {
+        self.cache_downloads.clone()
+    }
+
+    pub fn ops_client_settings(&self) -> OpsClientSettings {
+        self.ops_client_settings.clone()
+    }
 }
@@ -1,4 +1,5 @@
 use crate::PatentReferenceType;
+use crate::xml::MIXED_TEXT_KEY;
 use chrono::NaiveDate;
 use serde::Deserialize;
 use serde::de::Deserializer;
@@ -66,6 +67,143 @@ pub struct TokenResponse {
     pub token_type: String,
 }
 
+/* Helpers shared by the published-data style JSON (family, biblio, legal, ...)
+ *  OPS turns the XML into JSON mechanically - text ends up under "$", attributes get an "@" prefix
+ *  and an element appearing once isn't wrapped in an array.
+ */
+
+// Many goes first so a list of serde_json::Values doesn't get taken as One big Value
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum OneOrMany<T> {
+    Many(Vec<T>),
+    One(T),
+}
+
+impl<T> Default for OneOrMany<T> {
+    fn default() -> Self {
+        OneOrMany::Many(Vec::new())
+    }
+}
+
+impl<T> OneOrMany<T> {
+    pub(crate) fn into_vec(self) -> Vec<T> {
+        match self {
+            OneOrMany::One(one) => vec![one],
+            OneOrMany::Many(many) => many,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct Text {
+    #[serde(rename(deserialize = "$"))]
+    pub(crate) value: String,
+}
+
+/// A `document-id` as found in publication/application references and priority claims.
+#[derive(Debug, Deserialize)]
+pub(crate) struct DocumentId {
+    #[serde(rename(deserialize = "@document-id-type"))]
+    pub(crate) document_id_type: Option<String>,
+    pub(crate) country: Option<Text>,
+    #[serde(rename(deserialize = "doc-number"))]
+    pub(crate) doc_number: Text,
+    pub(crate) kind: Option<Text>,
+    pub(crate) date: Option<Text>,
+}
+
+// epodoc publication numbers can also have the kind code stuck on the end, e.g. "EP1000000A1"
+fn split_epodoc_kind(number: String) -> (String, Option<String>) {
+    // A letter, optionally followed by a digit, straight after the digits of the number
+    let without_digit = number
+        .strip_suffix(|c: char| c.is_ascii_digit())
+        .unwrap_or(&number);
+    match without_digit.char_indices().next_back() {
+        Some((i, c))
+            if c.is_ascii_uppercase() && number[..i].ends_with(|c: char| c.is_ascii_digit()) =>
+        {
+            (number[..i].to_string(), Some(number[i..].to_string()))
+        }
+        _ => (number, None),
+    }
+}
+
+impl DocumentId {
+    pub(crate) fn to_patent_details(&self, reference_type: PatentReferenceType) -> PatentDetails {
+        let doc_number = self.doc_number.value.trim();
+        let (country_code, number) = match &self.country {
+            Some(country) => (country.value.clone(), doc_number.to_string()),
+            // epodoc numbers have the country stuck on the front
+            None => {
+                let split = doc_number
+                    .find(|c: char| !c.is_ascii_alphabetic())
+                    .unwrap_or(doc_number.len());
+                (
+                    doc_number[..split].to_string(),
+                    doc_number[split..].to_string(),
+                )
+            }
+        };
+        let (number, kind_code) = match &self.kind {
+            Some(kind) => (number, Some(kind.value.clone())),
+            None if self.country.is_none() => split_epodoc_kind(number),
+            None => (number, None),
+        };
+        PatentDetails {
+            country_code,
+            number,
+            kind_code,
+            date: self
+                .date
+                .as_ref()
+                .and_then(|d| NaiveDate::parse_from_str(&d.value, "%Y%m%d").ok()),
+            reference_type,
+        }
+    }
+}
+
+/// A publication/application reference or priority claim - the same number in one or more
+/// formats.
+#[derive(Debug, Deserialize)]
+pub(crate) struct DocumentReference {
+    #[serde(rename(deserialize = "document-id"))]
+    pub(crate) document_id: OneOrMany<DocumentId>,
+}
+
+/// Picks the docdb flavour out of the document-ids given for one reference (it's the one with the
+/// kind code), falling back to whatever came first.
+pub(crate) fn preferred_document_id(
+    document_ids: OneOrMany<DocumentId>,
+    reference_type: PatentReferenceType,
+) -> Option<PatentDetails> {
+    let document_ids = document_ids.into_vec();
+    document_ids
+        .iter()
+        .find(|id| id.document_id_type.as_deref() == Some("docdb"))
+        .or_else(|| document_ids.first())
+        .map(|id| id.to_patent_details(reference_type))
+}
+
+/// All the text within an element, for the places where OPS allows markup (e.g. `<b>`) in
+/// between the text.
+pub(crate) fn text_content(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(text) => text.clone(),
+        serde_json::Value::Array(values) => values.iter().map(text_content).collect(),
+        serde_json::Value::Object(map) => match map.get(MIXED_TEXT_KEY) {
+            // Read from XML, where the text of mixed content was kept in order
+            Some(serde_json::Value::String(text)) => text.clone(),
+            _ => map
+                .iter()
+                .filter(|(key, _)| !key.starts_with('@'))
+                .map(|(_, value)| text_content(value))
+                .collect(),
+        },
+        _ => String::new(),
+    }
+}
+
 fn parse_application_number<'de, D>(deserializer: D) -> Result<PatentDetails, D::Error>
 where
     D: Deserializer<'de>,
@@ -99,7 +237,9 @@ where
 
     let date = reg_doc_id
         .reg_date
-        .map(|d| NaiveDate::parse_from_str(&d.value, "%Y%m%d").unwrap());
+        .map(|d| NaiveDate::parse_from_str(&d.value, "%Y%m%d"))
+        .transpose()
+        .map_err(serde::de::Error::custom)?;
     Ok(PatentDetails {
         country_code: reg_doc_id.reg_country.value.clone(),
         number: reg_doc_id.reg_doc_number.value.clone(),
@@ -235,4 +375,16 @@ mod tests {
             serde_json::from_reader(reader);
         assert!(json.is_ok());
     }
+
+    #[test]
+    fn test_bad_register_date() {
+        let json = r#"{"reg:document-id": {
+            "reg:country": {"$": "EP"},
+            "reg:doc-number": {"$": "13860559"},
+            "reg:date": {"$": "2013-12-02"}
+        }}"#;
+        let reference: Result<RegApplicationReference, serde_json::Error> =
+            serde_json::from_str(json);
+        assert!(reference.is_err());
+    }
 }
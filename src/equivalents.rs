@@ -0,0 +1,213 @@
+use crate::abstracts::exchange_document_abstracts;
+use crate::deser::{DocumentReference, OneOrMany, preferred_document_id};
+use crate::exchange::{Exchange, Single};
+use crate::ops_client::{ClientCore, OpsRequest, any_reference_type_path};
+use crate::{Abstract, OpsError, OpsResponse, PatentDetails, PatentReferenceType, ServiceKind};
+use reqwest::header::HeaderMap;
+use serde::Deserialize;
+use serde_json::Value;
+use std::fmt;
+
+pub enum EquivalentsConstituents {
+    Biblio,
+    Abstract,
+    Images,
+}
+
+impl fmt::Display for EquivalentsConstituents {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EquivalentsConstituents::Biblio => write!(f, "biblio"),
+            EquivalentsConstituents::Abstract => write!(f, "abstract"),
+            EquivalentsConstituents::Images => write!(f, "images"),
+        }
+    }
+}
+
+/// One publication in the simple family.
+#[derive(Debug)]
+pub struct Equivalent {
+    pub publication: PatentDetails,
+    /// The `exchange-document`, only there when the biblio or abstract constituent was asked for.
+    pub biblio: Option<Value>,
+    /// Only there when the abstract constituent was asked for.
+    pub abstracts: Vec<Abstract>,
+}
+
+/* Equivalents structs
+ *  These are for deserialising the JSON from the equivalents (simple family) service. The
+ *  exchange-document of the biblio/abstract constituents is kept as it came, the images
+ *  constituent is left in the raw response.
+ */
+
+#[derive(Debug, Deserialize)]
+struct EquExchangeDocuments {
+    #[serde(rename(deserialize = "exchange-document"), default)]
+    exchange_document: OneOrMany<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EquInquiryResult {
+    #[serde(rename(deserialize = "publication-reference"))]
+    publication_reference: Option<DocumentReference>,
+    #[serde(rename(deserialize = "exchange-documents"))]
+    exchange_documents: Option<EquExchangeDocuments>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EquEquivalentsInquiry {
+    #[serde(rename(deserialize = "ops:inquiry-result"), default)]
+    ops_inquiry_result: OneOrMany<EquInquiryResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EquWorldPatentData {
+    #[serde(rename(deserialize = "ops:equivalents-inquiry"))]
+    ops_equivalents_inquiry: EquEquivalentsInquiry,
+}
+
+#[derive(Debug, Deserialize)]
+struct EquResult {
+    #[serde(rename(deserialize = "ops:world-patent-data"))]
+    ops_world_patent_data: EquWorldPatentData,
+}
+
+/// The publications in the simple family, which includes the one asked about.
+pub(crate) fn parse_equivalents(response: &OpsResponse) -> Result<Vec<Equivalent>, OpsError> {
+    let result: EquResult = response.json()?;
+    let mut equivalents = Vec::new();
+    for inquiry_result in result
+        .ops_world_patent_data
+        .ops_equivalents_inquiry
+        .ops_inquiry_result
+        .into_vec()
+    {
+        let Some(publication) = inquiry_result
+            .publication_reference
+            .and_then(|r| preferred_document_id(r.document_id, PatentReferenceType::Publication))
+        else {
+            continue;
+        };
+        let biblio = inquiry_result
+            .exchange_documents
+            .and_then(|d| d.exchange_document.into_vec().into_iter().next());
+        let abstracts = match &biblio {
+            Some(exchange_document) => exchange_document_abstracts(exchange_document)?,
+            None => Vec::new(),
+        };
+        equivalents.push(Equivalent {
+            publication,
+            biblio,
+            abstracts,
+        });
+    }
+    Ok(equivalents)
+}
+
+impl ClientCore {
+    pub(crate) fn equivalents_request(
+        &self,
+        patent_details: &PatentDetails,
+        constituents: &[EquivalentsConstituents],
+    ) -> Result<OpsRequest, OpsError> {
+        let mut url = any_reference_type_path(
+            patent_details,
+            &self.settings.endpoints.service_url("published-data"),
+        )?;
+        url.push_str("equivalents");
+        for (i, constituent) in constituents.iter().enumerate() {
+            url.push(if i == 0 { '/' } else { ',' });
+            url.push_str(&constituent.to_string());
+        }
+        let mut headers = HeaderMap::new();
+        headers.insert("Content-Type", "text/plain".parse().unwrap());
+        headers.insert("Accept", self.accept());
+        Ok(OpsRequest::post(
+            &url,
+            Some(patent_details.to_string()),
+            headers,
+            ServiceKind::Retrieval,
+        ))
+    }
+
+    pub(crate) fn get_equivalents_response(
+        &self,
+        patent_details: &PatentDetails,
+        constituents: &[EquivalentsConstituents],
+    ) -> Result<Single, OpsError> {
+        Ok(Single::new(
+            self.equivalents_request(patent_details, constituents)?,
+        ))
+    }
+
+    pub(crate) fn get_equivalents(
+        &self,
+        patent_details: &PatentDetails,
+        constituents: &[EquivalentsConstituents],
+    ) -> Result<impl Exchange<Output = Vec<Equivalent>>, OpsError> {
+        Ok(self
+            .get_equivalents_response(patent_details, constituents)?
+            .map(|response| parse_equivalents(&response)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ops_client::test_core;
+    use crate::response::fixture_response;
+
+    #[test]
+    fn test_equivalents_deser() {
+        let response = fixture_response("test/example_equivalents.json");
+        let equivalents = parse_equivalents(&response).unwrap();
+        let numbers: Vec<String> = equivalents
+            .iter()
+            .map(|e| e.publication.to_string())
+            .collect();
+        assert_eq!(numbers, vec!["EP1000000A1", "US6093011A", "EP1000000B1"]);
+        let us = &equivalents[1];
+        assert_eq!(us.publication.country_code, "US");
+        assert_eq!(us.publication.number, "6093011");
+        assert_eq!(us.publication.kind_code.as_deref(), Some("A"));
+        assert!(us.biblio.is_some());
+        assert_eq!(us.abstracts[0].language.as_deref(), Some("en"));
+        assert!(equivalents[0].biblio.is_none());
+        assert!(equivalents[0].abstracts.is_empty());
+    }
+
+    #[test]
+    fn test_equivalents_request() {
+        let core = test_core();
+        let mut patent_details = PatentDetails {
+            country_code: "EP".to_string(),
+            number: "1000000".to_string(),
+            kind_code: None,
+            date: None,
+            reference_type: PatentReferenceType::Publication,
+        };
+        assert_eq!(
+            core.equivalents_request(&patent_details, &[]).unwrap().url,
+            "https://ops.epo.org/3.2/rest-services/published-data/publication/epodoc/equivalents"
+        );
+        assert_eq!(
+            core.equivalents_request(
+                &patent_details,
+                &[
+                    EquivalentsConstituents::Biblio,
+                    EquivalentsConstituents::Images
+                ]
+            )
+            .unwrap()
+            .url,
+            "https://ops.epo.org/3.2/rest-services/published-data/publication/epodoc/equivalents/biblio,images"
+        );
+        patent_details.reference_type = PatentReferenceType::Priority;
+        assert_eq!(
+            core.equivalents_request(&patent_details, &[]).unwrap().url,
+            "https://ops.epo.org/3.2/rest-services/published-data/priority/epodoc/equivalents"
+        );
+        patent_details.reference_type = PatentReferenceType::Unknown;
+        assert!(core.equivalents_request(&patent_details, &[]).is_err());
+    }
+}
@@ -0,0 +1,266 @@
+use crate::xml::xml_to_value;
+use reqwest::StatusCode;
+use reqwest::header::HeaderMap;
+use serde_json::Value;
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+/// Everything that can go wrong when talking to EPO OPS.
+///
+/// The variants are split by what the caller can sensibly do about them: transport problems and
+/// throttling are usually worth retrying, faults such as "not found" are not.
+#[derive(Debug)]
+pub enum OpsError {
+    /// The request never got a response - DNS, TLS, connection resets etc.
+    Transport(reqwest::Error),
+    /// A non-success status where the body didn't contain anything we could make sense of.
+    HttpStatus { status: StatusCode, body: String },
+    /// OPS answered with a fault document (section 3.6 of the EPO OPS doc).
+    OpsFault {
+        status: StatusCode,
+        code: String,
+        message: String,
+    },
+    /// The credentials or the access token were rejected.
+    Auth { status: StatusCode, message: String },
+    /// The request was rejected by OPS's throttling.
+    Throttled {
+        retry_after: Option<Duration>,
+        message: String,
+    },
+    /// One of the fair use quotas has been used up.
+    QuotaExceeded { reason: String },
+    /// The body came back fine, but not in the layout we expected.
+    Decode(Box<dyn Error + Send + Sync>),
+    /// The request couldn't be built from what we were given, e.g. an unsupported reference type.
+    InvalidInput(String),
+    /// The client settings can't be used, e.g. a plain http base URL.
+    InvalidConfig(String),
+    /// Reading or writing downloaded files failed.
+    Io(std::io::Error),
+}
+
+impl fmt::Display for OpsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OpsError::Transport(e) => write!(f, "error sending request to EPO OPS: {}", e),
+            OpsError::HttpStatus { status, body } => {
+                write!(f, "EPO OPS answered with {}: {}", status, body)
+            }
+            OpsError::OpsFault {
+                status,
+                code,
+                message,
+            } => write!(f, "EPO OPS fault {} ({}): {}", code, status, message),
+            OpsError::Auth { status, message } => {
+                write!(f, "EPO OPS authentication failed ({}): {}", status, message)
+            }
+            OpsError::Throttled {
+                retry_after,
+                message,
+            } => {
+                write!(f, "EPO OPS throttled the request: {}", message)?;
+                if let Some(retry_after) = retry_after {
+                    write!(f, " (retry after {}s)", retry_after.as_secs())?;
+                }
+                Ok(())
+            }
+            OpsError::QuotaExceeded { reason } => {
+                write!(f, "EPO OPS fair use quota exceeded: {}", reason)
+            }
+            OpsError::Decode(e) => write!(f, "couldn't decode EPO OPS response: {}", e),
+            OpsError::InvalidInput(message) => write!(f, "invalid request: {}", message),
+            OpsError::InvalidConfig(message) => write!(f, "invalid configuration: {}", message),
+            OpsError::Io(e) => write!(f, "file error: {}", e),
+        }
+    }
+}
+
+impl Error for OpsError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            OpsError::Transport(e) => Some(e),
+            OpsError::Decode(e) => Some(e.as_ref()),
+            OpsError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for OpsError {
+    fn from(e: reqwest::Error) -> Self {
+        OpsError::Transport(e)
+    }
+}
+
+impl From<std::io::Error> for OpsError {
+    fn from(e: std::io::Error) -> Self {
+        OpsError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for OpsError {
+    fn from(e: serde_json::Error) -> Self {
+        OpsError::Decode(Box::new(e))
+    }
+}
+
+impl OpsError {
+    /// Turns a non-success response into the most specific error we can.
+    pub(crate) fn from_response(status: StatusCode, headers: &HeaderMap, body: String) -> Self {
+        let fault = parse_fault(&body);
+
+        if let Some(reason) = header_str(headers, "X-Rejection-Reason") {
+            // Quota rejections are reported as e.g. "IndividualQuotaPerHour" or
+            //  "RegisteredQuotaPerWeek". Anything else is the throttling kicking in.
+            if reason.contains("Quota") {
+                return OpsError::QuotaExceeded {
+                    reason: reason.to_string(),
+                };
+            }
+            return OpsError::Throttled {
+                retry_after: parse_retry_after(headers),
+                message: reason.to_string(),
+            };
+        }
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            return OpsError::Throttled {
+                retry_after: parse_retry_after(headers),
+                message: fault.map(|(_, message)| message).unwrap_or(body),
+            };
+        }
+
+        match fault {
+            Some((code, message)) => {
+                if status == StatusCode::UNAUTHORIZED || is_token_fault(&code) {
+                    OpsError::Auth { status, message }
+                } else if code.contains("Quota") {
+                    OpsError::QuotaExceeded { reason: message }
+                } else {
+                    OpsError::OpsFault {
+                        status,
+                        code,
+                        message,
+                    }
+                }
+            }
+            None if status == StatusCode::UNAUTHORIZED => OpsError::Auth {
+                status,
+                message: body,
+            },
+            None => OpsError::HttpStatus { status, body },
+        }
+    }
+}
+
+fn is_token_fault(code: &str) -> bool {
+    // OPS itself uses CLIENT.InvalidAccessToken, the gateway in front of it uses things like
+    //  oauth.v2.InvalidAccessToken or keymanagement.service.invalid_access_token
+    let code = code.to_lowercase();
+    code.contains("accesstoken")
+        || code.contains("access_token")
+        || code.contains("invalidclient")
+        || code.contains("invalid_client")
+}
+
+fn header_str<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|v| v.to_str().ok())
+}
+
+pub(crate) fn parse_retry_after(headers: &HeaderMap) -> Option<Duration> {
+    // Retry-After can also be an HTTP date, but OPS only ever seems to send seconds
+    header_str(headers, "Retry-After")
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .map(Duration::from_secs)
+}
+
+/// Pulls the fault code and message out of an error body.
+///
+/// OPS sends its faults as XML even when JSON was asked for, while the gateway handling
+/// authentication answers in JSON, so both are handled here.
+fn parse_fault(body: &str) -> Option<(String, String)> {
+    // XML faults are read into the same layout as the JSON ones
+    let json = serde_json::from_str::<Value>(body)
+        .ok()
+        .or_else(|| xml_to_value(body.as_bytes()).ok())?;
+    let fault = json
+        .get("fault")
+        .or_else(|| json.get("ops:fault"))
+        .unwrap_or(&json);
+    let code = fault
+        .get("code")
+        .or_else(|| fault.pointer("/detail/errorcode"))
+        .or_else(|| fault.get("error"))
+        .and_then(json_text);
+    let message = fault
+        .get("message")
+        .or_else(|| fault.get("faultstring"))
+        .or_else(|| fault.get("error_description"))
+        .and_then(json_text);
+    match (code, message) {
+        (Some(code), Some(message)) => Some((code, message)),
+        (Some(code), None) => Some((code.clone(), code)),
+        (None, Some(message)) => Some((String::new(), message)),
+        (None, None) => None,
+    }
+}
+
+fn json_text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        // OPS style JSON wraps text in {"$": "..."}
+        Value::Object(o) => o
+            .get("$")
+            .and_then(|v| v.as_str())
+            .map(|s| s.trim().to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fault_classification() {
+        let body = r#"<?xml version="1.0" encoding="UTF-8"?>
+<fault xmlns="http://ops.epo.org">
+    <code>SERVER.EntityNotFound</code>
+    <message>No results found</message>
+</fault>"#;
+        match OpsError::from_response(StatusCode::NOT_FOUND, &HeaderMap::new(), body.to_string()) {
+            OpsError::OpsFault { code, message, .. } => {
+                assert_eq!(code, "SERVER.EntityNotFound");
+                assert_eq!(message, "No results found");
+            }
+            e => panic!("Wrong error: {:?}", e),
+        }
+
+        let body = r#"{"fault":{"faultstring":"Invalid access token","detail":{"errorcode":"oauth.v2.InvalidAccessToken"}}}"#;
+        assert!(matches!(
+            OpsError::from_response(StatusCode::BAD_REQUEST, &HeaderMap::new(), body.to_string()),
+            OpsError::Auth { .. }
+        ));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "X-Rejection-Reason",
+            "RegisteredQuotaPerWeek".parse().unwrap(),
+        );
+        assert!(matches!(
+            OpsError::from_response(StatusCode::FORBIDDEN, &headers, String::new()),
+            OpsError::QuotaExceeded { .. }
+        ));
+
+        let mut headers = HeaderMap::new();
+        headers.insert("X-Rejection-Reason", "SystemOverloaded".parse().unwrap());
+        headers.insert("Retry-After", "30".parse().unwrap());
+        match OpsError::from_response(StatusCode::FORBIDDEN, &headers, String::new()) {
+            OpsError::Throttled { retry_after, .. } => {
+                assert_eq!(retry_after, Some(Duration::from_secs(30)))
+            }
+            e => panic!("Wrong error: {:?}", e),
+        }
+    }
+}
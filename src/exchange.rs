@@ -0,0 +1,208 @@
+use crate::ops_client::{OpsRequest, remaining_search_ranges};
+use crate::{OpsError, OpsResponse};
+use log::debug;
+
+/// What a client method does with OPS, apart from the sending: which requests to make and what
+/// to make of their responses. The blocking and async clients both only send what `next_request`
+/// asks for and hand the outcome to `handle`, so everything else is shared between them.
+pub(crate) trait Exchange {
+    type Output;
+
+    /// The next request to send, or `None` once there's nothing left to ask for.
+    fn next_request(&mut self) -> Result<Option<OpsRequest>, OpsError>;
+
+    /// Takes the outcome of the last request from `next_request`. An error returned from here
+    /// stops the exchange.
+    fn handle(&mut self, response: Result<OpsResponse, OpsError>) -> Result<(), OpsError>;
+
+    fn finish(self) -> Result<Self::Output, OpsError>;
+
+    /// The same requests, with the output read by `read` - e.g. the typed version of a call that
+    /// otherwise gives back the raw responses.
+    fn map<T, F>(self, read: F) -> Map<Self, F>
+    where
+        Self: Sized,
+        F: FnOnce(Self::Output) -> Result<T, OpsError>,
+    {
+        Map {
+            exchange: self,
+            read,
+        }
+    }
+}
+
+pub(crate) struct Map<E, F> {
+    exchange: E,
+    read: F,
+}
+
+impl<E, F, T> Exchange for Map<E, F>
+where
+    E: Exchange,
+    F: FnOnce(E::Output) -> Result<T, OpsError>,
+{
+    type Output = T;
+
+    fn next_request(&mut self) -> Result<Option<OpsRequest>, OpsError> {
+        self.exchange.next_request()
+    }
+
+    fn handle(&mut self, response: Result<OpsResponse, OpsError>) -> Result<(), OpsError> {
+        self.exchange.handle(response)
+    }
+
+    fn finish(self) -> Result<T, OpsError> {
+        (self.read)(self.exchange.finish()?)
+    }
+}
+
+/// One request, giving back its response.
+pub(crate) struct Single {
+    request: Option<OpsRequest>,
+    response: Option<OpsResponse>,
+}
+
+impl Single {
+    pub(crate) fn new(request: OpsRequest) -> Self {
+        Single {
+            request: Some(request),
+            response: None,
+        }
+    }
+}
+
+impl Exchange for Single {
+    type Output = OpsResponse;
+
+    fn next_request(&mut self) -> Result<Option<OpsRequest>, OpsError> {
+        Ok(self.request.take())
+    }
+
+    fn handle(&mut self, response: Result<OpsResponse, OpsError>) -> Result<(), OpsError> {
+        self.response = Some(response?);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<OpsResponse, OpsError> {
+        self.response
+            .ok_or_else(|| OpsError::Decode("No response to read".into()))
+    }
+}
+
+type PageRequest<'a> = Box<dyn Fn(&str) -> Result<OpsRequest, OpsError> + Send + 'a>;
+
+/// Every page of a search, one response per page. The first page ("1-100") says how many results
+/// there are, the rest are asked for 100 at a time after it.
+pub(crate) struct Pages<'a> {
+    target: &'static str,
+    request: PageRequest<'a>,
+    // Reads the number of results to get out of the first page
+    total: fn(&OpsResponse) -> Result<u32, OpsError>,
+    no_results: Option<fn(&OpsError) -> bool>,
+    pages: Vec<OpsResponse>,
+    // None until the first page is in
+    ranges: Option<std::vec::IntoIter<String>>,
+}
+
+impl<'a> Pages<'a> {
+    /// `target` is what the pages are logged under, e.g. "register".
+    pub(crate) fn new(
+        target: &'static str,
+        request: impl Fn(&str) -> Result<OpsRequest, OpsError> + Send + 'a,
+        total: fn(&OpsResponse) -> Result<u32, OpsError>,
+    ) -> Self {
+        Pages {
+            target,
+            request: Box::new(request),
+            total,
+            no_results: None,
+            pages: Vec::new(),
+            ranges: None,
+        }
+    }
+
+    /// For services that answer a search without any hits with an error rather than an empty
+    /// page: a first page failing with an error `no_results` picks out gives no pages at all.
+    pub(crate) fn or_none_when(mut self, no_results: fn(&OpsError) -> bool) -> Self {
+        self.no_results = Some(no_results);
+        self
+    }
+}
+
+impl Exchange for Pages<'_> {
+    type Output = Vec<OpsResponse>;
+
+    fn next_request(&mut self) -> Result<Option<OpsRequest>, OpsError> {
+        let range = match &mut self.ranges {
+            None => "1-100".to_string(),
+            Some(ranges) => match ranges.next() {
+                Some(range) => range,
+                None => return Ok(None),
+            },
+        };
+        if self.ranges.is_some() {
+            debug!(target: self.target, "Getting items {} of the current search", range);
+        }
+        (self.request)(&range).map(Some)
+    }
+
+    fn handle(&mut self, response: Result<OpsResponse, OpsError>) -> Result<(), OpsError> {
+        let response = match response {
+            Err(e) if self.ranges.is_none() && self.no_results.is_some_and(|f| f(&e)) => {
+                debug!(target: self.target, "No results found");
+                self.ranges = Some(Vec::new().into_iter());
+                return Ok(());
+            }
+            response => response?,
+        };
+        if self.ranges.is_none() {
+            let total = (self.total)(&response)?;
+            self.ranges = Some(remaining_search_ranges(total).into_iter());
+        }
+        self.pages.push(response);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Vec<OpsResponse>, OpsError> {
+        Ok(self.pages)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ops_client::ClientCore;
+    use crate::{EpoOpsCredentials, OpsClientSettings};
+    use reqwest::StatusCode;
+    use reqwest::header::HeaderMap;
+    use std::time::Duration;
+
+    #[test]
+    fn test_pages() {
+        let core = ClientCore::new(
+            EpoOpsCredentials::new("key", "secret"),
+            OpsClientSettings::default(),
+        )
+        .unwrap();
+        let mut pages = Pages::new(
+            "register",
+            |range| Ok(core.register_search_request("pa=ibm", range)),
+            |_| Ok(250),
+        )
+        .map(|pages| Ok(pages.len()));
+        let mut ranges = Vec::new();
+        while let Some(request) = pages.next_request().unwrap() {
+            ranges.push(request.headers["Range"].to_str().unwrap().to_string());
+            let page = OpsResponse::new(
+                StatusCode::OK,
+                HeaderMap::new(),
+                Vec::new(),
+                "",
+                Duration::ZERO,
+            );
+            pages.handle(Ok(page)).unwrap();
+        }
+        assert_eq!(ranges, vec!["1-100", "101-200", "201-300"]);
+        assert_eq!(pages.finish().unwrap(), 3);
+    }
+}
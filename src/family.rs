@@ -0,0 +1,237 @@
+use crate::deser::{DocumentReference, OneOrMany, preferred_document_id};
+use crate::exchange::{Exchange, Single};
+use crate::legal::OpsLegal;
+use crate::ops_client::{ClientCore, OpsRequest, any_reference_type_path};
+use crate::{LegalEvent, OpsError, OpsResponse, PatentDetails, PatentReferenceType, ServiceKind};
+use reqwest::header::HeaderMap;
+use serde::Deserialize;
+use serde_json::Value;
+use std::fmt;
+
+pub enum FamilyConstituents {
+    Biblio,
+    Legal,
+}
+
+impl fmt::Display for FamilyConstituents {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FamilyConstituents::Biblio => write!(f, "biblio"),
+            FamilyConstituents::Legal => write!(f, "legal"),
+        }
+    }
+}
+
+/// An INPADOC patent family - section 3.2 of the EPO OPS doc.
+#[derive(Debug)]
+pub struct Family {
+    pub family_id: Option<String>,
+    pub members: Vec<FamilyMember>,
+}
+
+#[derive(Debug)]
+pub struct FamilyMember {
+    pub family_id: String,
+    pub publication: Option<PatentDetails>,
+    pub application: Option<PatentDetails>,
+    pub priorities: Vec<PatentDetails>,
+    /// The `exchange-document`, only there when the biblio constituent was asked for.
+    pub biblio: Option<Value>,
+    /// Only there when the legal constituent was asked for.
+    pub legal: Vec<LegalEvent>,
+}
+
+/* Family structs
+ *  These are for deserialising the JSON from family retrieval (and legal retrieval, which answers
+ *  in the same layout). The biblio part is kept as it came.
+ */
+
+#[derive(Debug, Deserialize)]
+struct FamilyOpsFamilyMember {
+    #[serde(rename(deserialize = "@family-id"))]
+    family_id: String,
+    #[serde(rename(deserialize = "publication-reference"))]
+    publication_reference: Option<DocumentReference>,
+    #[serde(rename(deserialize = "application-reference"))]
+    application_reference: Option<DocumentReference>,
+    #[serde(rename(deserialize = "priority-claim"), default)]
+    priority_claim: OneOrMany<DocumentReference>,
+    #[serde(rename(deserialize = "exchange-document"))]
+    exchange_document: Option<Value>,
+    #[serde(rename(deserialize = "ops:legal"), default)]
+    ops_legal: OneOrMany<OpsLegal>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FamilyOpsPatentFamily {
+    #[serde(rename(deserialize = "ops:family-member"), default)]
+    ops_family_member: OneOrMany<FamilyOpsFamilyMember>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FamilyOpsWorldPatentData {
+    #[serde(rename(deserialize = "ops:patent-family"))]
+    ops_patent_family: FamilyOpsPatentFamily,
+}
+
+#[derive(Debug, Deserialize)]
+struct FamilyResult {
+    #[serde(rename(deserialize = "ops:world-patent-data"))]
+    ops_world_patent_data: FamilyOpsWorldPatentData,
+}
+
+impl From<FamilyOpsFamilyMember> for FamilyMember {
+    fn from(member: FamilyOpsFamilyMember) -> Self {
+        FamilyMember {
+            family_id: member.family_id,
+            publication: member.publication_reference.and_then(|r| {
+                preferred_document_id(r.document_id, PatentReferenceType::Publication)
+            }),
+            application: member.application_reference.and_then(|r| {
+                preferred_document_id(r.document_id, PatentReferenceType::Application)
+            }),
+            priorities: member
+                .priority_claim
+                .into_vec()
+                .into_iter()
+                .filter_map(|r| preferred_document_id(r.document_id, PatentReferenceType::Priority))
+                .collect(),
+            biblio: member.exchange_document,
+            legal: member
+                .ops_legal
+                .into_vec()
+                .into_iter()
+                .map(LegalEvent::from)
+                .collect(),
+        }
+    }
+}
+
+pub(crate) fn parse_family(response: &OpsResponse) -> Result<Family, OpsError> {
+    let result: FamilyResult = response.json()?;
+    let members: Vec<FamilyMember> = result
+        .ops_world_patent_data
+        .ops_patent_family
+        .ops_family_member
+        .into_vec()
+        .into_iter()
+        .map(FamilyMember::from)
+        .collect();
+    Ok(Family {
+        family_id: members.first().map(|m| m.family_id.clone()),
+        members,
+    })
+}
+
+impl ClientCore {
+    pub(crate) fn family_request(
+        &self,
+        patent_details: &PatentDetails,
+        constituents: &[FamilyConstituents],
+    ) -> Result<OpsRequest, OpsError> {
+        let mut url = any_reference_type_path(
+            patent_details,
+            &self.settings.endpoints.service_url("family"),
+        )?;
+        // Unlike the other services, no constituent at all is fine - that's just the members
+        if constituents.is_empty() {
+            url.pop();
+        } else {
+            let constituents: Vec<String> = constituents.iter().map(|c| c.to_string()).collect();
+            url.push_str(&constituents.join(","));
+        }
+        let mut headers = HeaderMap::new();
+        headers.insert("Content-Type", "text/plain".parse().unwrap());
+        headers.insert("Accept", self.accept());
+        Ok(OpsRequest::post(
+            &url,
+            Some(patent_details.to_string()),
+            headers,
+            ServiceKind::Inpadoc,
+        ))
+    }
+
+    pub(crate) fn get_family_response(
+        &self,
+        patent_details: &PatentDetails,
+        constituents: &[FamilyConstituents],
+    ) -> Result<Single, OpsError> {
+        Ok(Single::new(
+            self.family_request(patent_details, constituents)?,
+        ))
+    }
+
+    /// The INPADOC family of a publication, application or priority number.
+    pub(crate) fn get_family(
+        &self,
+        patent_details: &PatentDetails,
+        constituents: &[FamilyConstituents],
+    ) -> Result<impl Exchange<Output = Family>, OpsError> {
+        Ok(self
+            .get_family_response(patent_details, constituents)?
+            .map(|response| parse_family(&response)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ops_client::test_core;
+    use crate::response::fixture_response;
+
+    #[test]
+    fn test_family_deser() {
+        let response = fixture_response("test/example_family.json");
+        let family = parse_family(&response).unwrap();
+        assert_eq!(family.family_id.as_deref(), Some("19768124"));
+        assert_eq!(family.members.len(), 2);
+
+        let first = &family.members[0];
+        let publication = first.publication.as_ref().unwrap();
+        assert_eq!(publication.to_string(), "EP1000000A1.20000517");
+        assert_eq!(
+            first.application.as_ref().unwrap().reference_type,
+            PatentReferenceType::Application
+        );
+        assert_eq!(first.priorities.len(), 1);
+        assert_eq!(first.priorities[0].country_code, "NL");
+        assert!(first.biblio.is_some());
+        assert_eq!(first.legal.len(), 1);
+        assert_eq!(first.legal[0].code, "AK");
+
+        // Only the epodoc document-id and a single priority claim
+        let second = &family.members[1];
+        let publication = second.publication.as_ref().unwrap();
+        assert_eq!(publication.country_code, "US");
+        assert_eq!(publication.number, "6093011");
+        assert_eq!(second.priorities.len(), 1);
+        assert!(second.legal.is_empty());
+    }
+
+    #[test]
+    fn test_family_request() {
+        let core = test_core();
+        let priority = PatentDetails {
+            country_code: "NL".to_string(),
+            number: "1010536".to_string(),
+            kind_code: None,
+            date: None,
+            reference_type: PatentReferenceType::Priority,
+        };
+        let request = core
+            .family_request(
+                &priority,
+                &[FamilyConstituents::Biblio, FamilyConstituents::Legal],
+            )
+            .unwrap();
+        assert_eq!(
+            request.url,
+            "https://ops.epo.org/3.2/rest-services/family/priority/epodoc/biblio,legal"
+        );
+        let request = core.family_request(&priority, &[]).unwrap();
+        assert_eq!(
+            request.url,
+            "https://ops.epo.org/3.2/rest-services/family/priority/epodoc"
+        );
+    }
+}
@@ -0,0 +1,231 @@
+use crate::deser::{DocumentReference, OneOrMany, preferred_document_id};
+use crate::exchange::{Exchange, Single};
+use crate::ops_client::ClientCore;
+use crate::{OpsError, OpsResponse, PatentDetails, PatentReferenceType, PublicationConstituents};
+use serde::Deserialize;
+use serde_json::Value;
+
+/// Every publication stage of an application, e.g. its A1 and B1 - what the full-cycle
+/// constituent of published-data retrieval returns (section 3.1.2 of the EPO OPS doc).
+#[derive(Debug)]
+pub struct FullCycle {
+    pub family_id: Option<String>,
+    pub application: Option<PatentDetails>,
+    /// Oldest first. Stages without a publication date go last.
+    pub stages: Vec<PublicationStage>,
+}
+
+#[derive(Debug)]
+pub struct PublicationStage {
+    /// With the kind code and publication date of this stage.
+    pub publication: PatentDetails,
+    /// The `exchange-document` of this stage.
+    pub biblio: Value,
+}
+
+impl PublicationStage {
+    /// Whether this stage is a granted patent - the B kind codes (including the B8/B9
+    /// corrections) and the C ones some offices (e.g. DE, CN) use for grants.
+    ///
+    /// Older US grants were published with kind A, which can't be told apart from an application
+    /// here.
+    pub fn is_grant(&self) -> bool {
+        self.publication
+            .kind_code
+            .as_deref()
+            .is_some_and(|kind| kind.starts_with('B') || kind.starts_with('C'))
+    }
+}
+
+impl FullCycle {
+    /// The most recently published stage.
+    pub fn latest_stage(&self) -> Option<&PublicationStage> {
+        self.stages
+            .iter()
+            .rfind(|stage| stage.publication.date.is_some())
+            .or_else(|| self.stages.last())
+    }
+
+    /// The first stage that's a grant, if there is one.
+    pub fn grant(&self) -> Option<&PublicationStage> {
+        self.stages.iter().find(|stage| stage.is_grant())
+    }
+
+    pub fn was_granted(&self) -> bool {
+        self.grant().is_some()
+    }
+}
+
+/* Full-cycle structs
+ *  These are for deserialising the JSON from published-data retrieval with the full-cycle
+ *  constituent - one exchange-documents per number asked for, with one exchange-document per
+ *  stage. Each exchange-document is kept as it came, only the references are read from it.
+ */
+
+#[derive(Debug, Deserialize)]
+struct CycleBibliographicData {
+    #[serde(rename(deserialize = "publication-reference"))]
+    publication_reference: Option<DocumentReference>,
+    #[serde(rename(deserialize = "application-reference"))]
+    application_reference: Option<DocumentReference>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CycleExchangeDocument {
+    #[serde(rename(deserialize = "@family-id"))]
+    family_id: Option<String>,
+    #[serde(rename(deserialize = "@country"))]
+    country: Option<String>,
+    #[serde(rename(deserialize = "@doc-number"))]
+    doc_number: Option<String>,
+    #[serde(rename(deserialize = "@kind"))]
+    kind: Option<String>,
+    #[serde(rename(deserialize = "bibliographic-data"))]
+    bibliographic_data: Option<CycleBibliographicData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CycleExchangeDocuments {
+    #[serde(rename(deserialize = "exchange-document"), default)]
+    exchange_document: OneOrMany<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CycleWorldPatentData {
+    #[serde(rename(deserialize = "exchange-documents"), default)]
+    exchange_documents: OneOrMany<CycleExchangeDocuments>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CycleResult {
+    #[serde(rename(deserialize = "ops:world-patent-data"))]
+    ops_world_patent_data: CycleWorldPatentData,
+}
+
+fn full_cycle(exchange_documents: CycleExchangeDocuments) -> Result<FullCycle, OpsError> {
+    let mut full_cycle = FullCycle {
+        family_id: None,
+        application: None,
+        stages: Vec::new(),
+    };
+    for exchange_document in exchange_documents.exchange_document.into_vec() {
+        let document = CycleExchangeDocument::deserialize(&exchange_document)?;
+        let (publication_reference, application_reference) = match document.bibliographic_data {
+            Some(biblio) => (biblio.publication_reference, biblio.application_reference),
+            None => (None, None),
+        };
+        // The publication-reference has the date, the attributes are there for when it's missing
+        let publication = publication_reference
+            .and_then(|r| preferred_document_id(r.document_id, PatentReferenceType::Publication))
+            .or_else(|| {
+                Some(PatentDetails {
+                    country_code: document.country?,
+                    number: document.doc_number?,
+                    kind_code: document.kind,
+                    date: None,
+                    reference_type: PatentReferenceType::Publication,
+                })
+            })
+            .ok_or_else(|| {
+                OpsError::Decode("exchange-document without a publication number".into())
+            })?;
+        if full_cycle.family_id.is_none() {
+            full_cycle.family_id = document.family_id;
+        }
+        if full_cycle.application.is_none() {
+            full_cycle.application = application_reference.and_then(|r| {
+                preferred_document_id(r.document_id, PatentReferenceType::Application)
+            });
+        }
+        full_cycle.stages.push(PublicationStage {
+            publication,
+            biblio: exchange_document,
+        });
+    }
+    // OPS lists the stages newest first
+    full_cycle
+        .stages
+        .sort_by_key(|stage| (stage.publication.date.is_none(), stage.publication.date));
+    Ok(full_cycle)
+}
+
+/// One `FullCycle` per number asked for.
+pub(crate) fn parse_full_cycles(response: &OpsResponse) -> Result<Vec<FullCycle>, OpsError> {
+    let result: CycleResult = response.json()?;
+    result
+        .ops_world_patent_data
+        .exchange_documents
+        .into_vec()
+        .into_iter()
+        .map(full_cycle)
+        .collect()
+}
+
+pub(crate) fn parse_full_cycle(response: &OpsResponse) -> Result<FullCycle, OpsError> {
+    parse_full_cycles(response)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| OpsError::Decode("No exchange-documents in the full-cycle response".into()))
+}
+
+impl ClientCore {
+    pub(crate) fn get_full_cycle_response(
+        &self,
+        patent_details: &PatentDetails,
+    ) -> Result<Single, OpsError> {
+        Ok(Single::new(self.publication_request(
+            patent_details,
+            &[PublicationConstituents::FullCycle],
+        )?))
+    }
+
+    pub(crate) fn get_full_cycle(
+        &self,
+        patent_details: &PatentDetails,
+    ) -> Result<impl Exchange<Output = FullCycle>, OpsError> {
+        Ok(self
+            .get_full_cycle_response(patent_details)?
+            .map(|response| parse_full_cycle(&response)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::response::fixture_response;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_full_cycle_deser() {
+        let response = fixture_response("test/example_full_cycle.json");
+        let full_cycle = parse_full_cycle(&response).unwrap();
+        assert_eq!(full_cycle.family_id.as_deref(), Some("19768124"));
+        assert_eq!(full_cycle.application.as_ref().unwrap().number, "99203729");
+
+        let kinds: Vec<&str> = full_cycle
+            .stages
+            .iter()
+            .map(|stage| stage.publication.kind_code.as_deref().unwrap())
+            .collect();
+        assert_eq!(kinds, vec!["A1", "B1"]);
+        assert_eq!(
+            full_cycle.stages[0].publication.date,
+            NaiveDate::from_ymd_opt(2000, 5, 17)
+        );
+        assert!(!full_cycle.stages[0].is_grant());
+        assert!(
+            full_cycle.stages[1]
+                .biblio
+                .pointer("/bibliographic-data/invention-title")
+                .is_some()
+        );
+
+        let latest = full_cycle.latest_stage().unwrap();
+        assert_eq!(latest.publication.to_string(), "EP1000000B1.20030903");
+        assert!(full_cycle.was_granted());
+        assert_eq!(
+            full_cycle.grant().unwrap().publication.kind_code.as_deref(),
+            Some("B1")
+        );
+    }
+}
@@ -0,0 +1,442 @@
+use crate::deser::{DocumentReference, OneOrMany, Text, preferred_document_id, text_content};
+use crate::exchange::{Exchange, Single};
+use crate::ops_client::{ClientCore, OpsRequest, reference_type_path};
+use crate::{
+    OpsError, OpsResponse, PatentDetails, PatentReferenceType, PublicationConstituents, ServiceKind,
+};
+use reqwest::header::HeaderMap;
+use serde::Deserialize;
+use serde_json::Value;
+
+/// Which full text parts OPS has for a publication - section 3.1.3 of the EPO OPS doc.
+#[derive(Debug)]
+pub struct FulltextInquiry {
+    pub publication: Option<PatentDetails>,
+    pub instances: Vec<FulltextInstance>,
+}
+
+#[derive(Debug)]
+pub struct FulltextInstance {
+    /// What the instance is, i.e. "description" or "claims".
+    pub kind: String,
+    pub formats: Vec<String>,
+    /// Empty when OPS doesn't say - the languages are then only known once the text is fetched.
+    pub languages: Vec<String>,
+}
+
+impl FulltextInquiry {
+    pub fn has_description(&self) -> bool {
+        self.instances.iter().any(|i| i.kind == "description")
+    }
+
+    pub fn has_claims(&self) -> bool {
+        self.instances.iter().any(|i| i.kind == "claims")
+    }
+}
+
+#[derive(Debug)]
+pub struct Description {
+    pub publication: Option<PatentDetails>,
+    pub language: Option<String>,
+    pub paragraphs: Vec<Paragraph>,
+}
+
+#[derive(Debug)]
+pub struct Paragraph {
+    /// The paragraph number as printed, e.g. "0001".
+    pub number: Option<String>,
+    pub text: String,
+}
+
+/// The claims of a publication in one language.
+#[derive(Debug)]
+pub struct Claims {
+    pub publication: Option<PatentDetails>,
+    pub language: Option<String>,
+    pub claims: Vec<Claim>,
+}
+
+#[derive(Debug)]
+pub struct Claim {
+    /// `None` only when the claims don't start with a numbered claim text.
+    pub number: Option<u32>,
+    pub text: String,
+}
+
+/* Full text structs
+ *  These are for deserialising the JSON from the fulltext, description and claims services.
+ */
+
+#[derive(Debug, Deserialize)]
+struct FtxtFulltextInstance {
+    #[serde(rename(deserialize = "@desc"))]
+    desc: String,
+    #[serde(rename(deserialize = "@lang"))]
+    lang: Option<String>,
+    #[serde(rename(deserialize = "ops:fulltext-format"), default)]
+    ops_fulltext_format: OneOrMany<Text>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FtxtInquiryResult {
+    #[serde(rename(deserialize = "publication-reference"))]
+    publication_reference: Option<DocumentReference>,
+    #[serde(rename(deserialize = "ops:fulltext-instance"), default)]
+    ops_fulltext_instance: OneOrMany<FtxtFulltextInstance>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FtxtFulltextInquiry {
+    #[serde(rename(deserialize = "ops:inquiry-result"), default)]
+    ops_inquiry_result: OneOrMany<FtxtInquiryResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FtxtInquiryWorldPatentData {
+    #[serde(rename(deserialize = "ops:fulltext-inquiry"))]
+    ops_fulltext_inquiry: FtxtFulltextInquiry,
+}
+
+#[derive(Debug, Deserialize)]
+struct FtxtInquiry {
+    #[serde(rename(deserialize = "ops:world-patent-data"))]
+    ops_world_patent_data: FtxtInquiryWorldPatentData,
+}
+
+#[derive(Debug, Deserialize)]
+struct FtxtDescription {
+    #[serde(rename(deserialize = "@lang"))]
+    lang: Option<String>,
+    #[serde(default)]
+    p: OneOrMany<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FtxtClaim {
+    #[serde(rename(deserialize = "claim-text"), default)]
+    claim_text: OneOrMany<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FtxtClaims {
+    #[serde(rename(deserialize = "@lang"))]
+    lang: Option<String>,
+    #[serde(default)]
+    claim: OneOrMany<FtxtClaim>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FtxtBibliographicData {
+    #[serde(rename(deserialize = "publication-reference"))]
+    publication_reference: Option<DocumentReference>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FtxtFulltextDocument {
+    #[serde(rename(deserialize = "bibliographic-data"))]
+    bibliographic_data: Option<FtxtBibliographicData>,
+    #[serde(default)]
+    description: OneOrMany<FtxtDescription>,
+    #[serde(default)]
+    claims: OneOrMany<FtxtClaims>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FtxtFulltextDocuments {
+    #[serde(rename(deserialize = "ftxt:fulltext-document"), default)]
+    ftxt_fulltext_document: OneOrMany<FtxtFulltextDocument>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FtxtWorldPatentData {
+    #[serde(rename(deserialize = "ftxt:fulltext-documents"))]
+    ftxt_fulltext_documents: FtxtFulltextDocuments,
+}
+
+#[derive(Debug, Deserialize)]
+struct FtxtResult {
+    #[serde(rename(deserialize = "ops:world-patent-data"))]
+    ops_world_patent_data: FtxtWorldPatentData,
+}
+
+fn publication_of(reference: Option<DocumentReference>) -> Option<PatentDetails> {
+    reference.and_then(|r| preferred_document_id(r.document_id, PatentReferenceType::Publication))
+}
+
+pub(crate) fn parse_fulltext_inquiry(response: &OpsResponse) -> Result<FulltextInquiry, OpsError> {
+    let result: FtxtInquiry = response.json()?;
+    let mut inquiry = FulltextInquiry {
+        publication: None,
+        instances: Vec::new(),
+    };
+    for inquiry_result in result
+        .ops_world_patent_data
+        .ops_fulltext_inquiry
+        .ops_inquiry_result
+        .into_vec()
+    {
+        if inquiry.publication.is_none() {
+            inquiry.publication = publication_of(inquiry_result.publication_reference);
+        }
+        for instance in inquiry_result.ops_fulltext_instance.into_vec() {
+            inquiry.instances.push(FulltextInstance {
+                kind: instance.desc,
+                formats: instance
+                    .ops_fulltext_format
+                    .into_vec()
+                    .into_iter()
+                    .map(|f| f.value)
+                    .collect(),
+                languages: instance
+                    .lang
+                    .map(|l| {
+                        l.split([',', ' '])
+                            .filter(|l| !l.is_empty())
+                            .map(str::to_string)
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+            });
+        }
+    }
+    Ok(inquiry)
+}
+
+fn parse_fulltext_documents(response: &OpsResponse) -> Result<Vec<FtxtFulltextDocument>, OpsError> {
+    let result: FtxtResult = response.json()?;
+    Ok(result
+        .ops_world_patent_data
+        .ftxt_fulltext_documents
+        .ftxt_fulltext_document
+        .into_vec())
+}
+
+/// One `Description` per language returned.
+pub(crate) fn parse_description(response: &OpsResponse) -> Result<Vec<Description>, OpsError> {
+    let mut descriptions = Vec::new();
+    for document in parse_fulltext_documents(response)? {
+        let publication = publication_of(
+            document
+                .bibliographic_data
+                .and_then(|b| b.publication_reference),
+        );
+        for description in document.description.into_vec() {
+            let paragraphs = description
+                .p
+                .into_vec()
+                .iter()
+                .map(|p| Paragraph {
+                    number: p.get("@num").and_then(Value::as_str).map(str::to_string),
+                    text: text_content(p).trim().to_string(),
+                })
+                .collect();
+            descriptions.push(Description {
+                publication: publication.clone(),
+                language: description.lang,
+                paragraphs,
+            });
+        }
+    }
+    Ok(descriptions)
+}
+
+// Claims come as "1. A method ..." - the number is only in the text. Anything else before a
+//  "." (e.g. "0.5 mm of ...") isn't a number.
+fn claim_number(text: &str) -> Option<u32> {
+    let (number, rest) = text.split_once('.')?;
+    if number.is_empty()
+        || !number.bytes().all(|b| b.is_ascii_digit())
+        || !rest.starts_with(char::is_whitespace)
+    {
+        return None;
+    }
+    number.parse().ok()
+}
+
+/// One `Claims` per language returned.
+pub(crate) fn parse_claims(response: &OpsResponse) -> Result<Vec<Claims>, OpsError> {
+    let mut all_claims = Vec::new();
+    for document in parse_fulltext_documents(response)? {
+        let publication = publication_of(
+            document
+                .bibliographic_data
+                .and_then(|b| b.publication_reference),
+        );
+        for claims in document.claims.into_vec() {
+            let claim_texts: Vec<String> = claims
+                .claim
+                .into_vec()
+                .into_iter()
+                .flat_map(|c| c.claim_text.into_vec())
+                .map(|t| text_content(&t).trim().to_string())
+                .filter(|t| !t.is_empty())
+                .collect();
+            // A claim can be split over several claim-texts, only the first has the number -
+            //  which is one on from the claim before, a fragment can start with any number
+            let mut claims_in_language: Vec<Claim> = Vec::new();
+            for text in claim_texts {
+                let number = claim_number(&text);
+                match claims_in_language.last_mut() {
+                    Some(previous)
+                        if number.is_none()
+                            || previous.number.is_some_and(|n| number != Some(n + 1)) =>
+                    {
+                        previous.text.push('\n');
+                        previous.text.push_str(&text);
+                    }
+                    _ => claims_in_language.push(Claim { number, text }),
+                }
+            }
+            all_claims.push(Claims {
+                publication: publication.clone(),
+                language: claims.lang,
+                claims: claims_in_language,
+            });
+        }
+    }
+    Ok(all_claims)
+}
+
+impl ClientCore {
+    /// The request for the fulltext inquiry, or the description or claims themselves.
+    pub(crate) fn fulltext_request(
+        &self,
+        patent_details: &PatentDetails,
+        part: &str,
+    ) -> Result<OpsRequest, OpsError> {
+        let mut url = reference_type_path(
+            patent_details,
+            &self.settings.endpoints.service_url("published-data"),
+        )?;
+        url.push_str(part);
+        let mut headers = HeaderMap::new();
+        headers.insert("Content-Type", "text/plain".parse().unwrap());
+        headers.insert("Accept", self.accept());
+        Ok(OpsRequest::post(
+            &url,
+            Some(patent_details.to_string()),
+            headers,
+            ServiceKind::Retrieval,
+        ))
+    }
+
+    pub(crate) fn fulltext_inquiry_request(
+        &self,
+        patent_details: &PatentDetails,
+    ) -> Result<OpsRequest, OpsError> {
+        self.fulltext_request(patent_details, "fulltext")
+    }
+
+    pub(crate) fn description_request(
+        &self,
+        patent_details: &PatentDetails,
+    ) -> Result<OpsRequest, OpsError> {
+        self.fulltext_request(
+            patent_details,
+            &PublicationConstituents::Description.to_string(),
+        )
+    }
+
+    pub(crate) fn claims_request(
+        &self,
+        patent_details: &PatentDetails,
+    ) -> Result<OpsRequest, OpsError> {
+        self.fulltext_request(patent_details, &PublicationConstituents::Claims.to_string())
+    }
+
+    pub(crate) fn get_fulltext_inquiry_response(
+        &self,
+        patent_details: &PatentDetails,
+    ) -> Result<Single, OpsError> {
+        Ok(Single::new(self.fulltext_inquiry_request(patent_details)?))
+    }
+
+    pub(crate) fn get_fulltext_inquiry(
+        &self,
+        patent_details: &PatentDetails,
+    ) -> Result<impl Exchange<Output = FulltextInquiry>, OpsError> {
+        Ok(self
+            .get_fulltext_inquiry_response(patent_details)?
+            .map(|response| parse_fulltext_inquiry(&response)))
+    }
+
+    pub(crate) fn get_description_response(
+        &self,
+        patent_details: &PatentDetails,
+    ) -> Result<Single, OpsError> {
+        Ok(Single::new(self.description_request(patent_details)?))
+    }
+
+    pub(crate) fn get_description(
+        &self,
+        patent_details: &PatentDetails,
+    ) -> Result<impl Exchange<Output = Vec<Description>>, OpsError> {
+        Ok(self
+            .get_description_response(patent_details)?
+            .map(|response| parse_description(&response)))
+    }
+
+    pub(crate) fn get_claims_response(
+        &self,
+        patent_details: &PatentDetails,
+    ) -> Result<Single, OpsError> {
+        Ok(Single::new(self.claims_request(patent_details)?))
+    }
+
+    pub(crate) fn get_claims(
+        &self,
+        patent_details: &PatentDetails,
+    ) -> Result<impl Exchange<Output = Vec<Claims>>, OpsError> {
+        Ok(self
+            .get_claims_response(patent_details)?
+            .map(|response| parse_claims(&response)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::response::fixture_response;
+
+    #[test]
+    fn test_fulltext_deser() {
+        let inquiry =
+            parse_fulltext_inquiry(&fixture_response("test/example_fulltext_inquiry.json"))
+                .unwrap();
+        assert!(inquiry.has_description());
+        assert!(inquiry.has_claims());
+        assert_eq!(inquiry.instances[1].languages, vec!["DE", "EN", "FR"]);
+        assert_eq!(inquiry.instances[0].formats, vec!["text-only"]);
+
+        let descriptions =
+            parse_description(&fixture_response("test/example_description.json")).unwrap();
+        assert_eq!(descriptions.len(), 1);
+        let description = &descriptions[0];
+        assert_eq!(description.language.as_deref(), Some("EN"));
+        assert_eq!(description.paragraphs.len(), 3);
+        assert_eq!(description.paragraphs[1].number.as_deref(), Some("0002"));
+        assert_eq!(
+            description.paragraphs[2].text,
+            "Bricks are formed in a mould."
+        );
+
+        let claims = parse_claims(&fixture_response("test/example_claims.json")).unwrap();
+        assert_eq!(claims.len(), 2);
+        assert_eq!(claims[1].language.as_deref(), Some("EN"));
+        assert_eq!(
+            claims[1].publication.as_ref().unwrap().to_string(),
+            "EP1000000B1"
+        );
+        // "0.5 mm", "2.5 %" and the out of sequence "1." all carry on claim 2
+        assert_eq!(claims[1].claims.len(), 3);
+        assert_eq!(claims[1].claims[1].number, Some(2));
+        assert!(claims[1].claims[1].text.starts_with("2. Apparatus"));
+        assert!(
+            claims[1].claims[1]
+                .text
+                .ends_with("\n2.5 % by weight of clay,\n1. as in claim 1.")
+        );
+        assert_eq!(claims[1].claims[2].number, Some(3));
+        assert_eq!(claims[0].claims.len(), 1);
+    }
+}
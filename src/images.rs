@@ -0,0 +1,459 @@
+use crate::deser::{DocumentReference, OneOrMany, Text, preferred_document_id};
+use crate::exchange::{Exchange, Single};
+use crate::ops_client::{ClientCore, OpsRequest, reference_type_path};
+use crate::{OpsError, OpsResponse, PatentDetails, PatentReferenceType, ServiceKind};
+use lopdf::{Document, Object, ObjectId, dictionary};
+use reqwest::header::HeaderMap;
+use serde::Deserialize;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// The kinds of images OPS has for a publication.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ImageKind {
+    FullDocument,
+    Drawing,
+    FirstPageClipping,
+    Other(String),
+}
+
+impl From<&str> for ImageKind {
+    fn from(desc: &str) -> Self {
+        match desc {
+            "FullDocument" => ImageKind::FullDocument,
+            "Drawing" => ImageKind::Drawing,
+            "FirstPageClipping" => ImageKind::FirstPageClipping,
+            other => ImageKind::Other(other.to_string()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageFormat {
+    Pdf,
+    Tiff,
+    Png,
+}
+
+impl ImageFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Pdf => "pdf",
+            ImageFormat::Tiff => "tiff",
+            ImageFormat::Png => "png",
+        }
+    }
+}
+
+impl fmt::Display for ImageFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImageFormat::Pdf => write!(f, "application/pdf"),
+            ImageFormat::Tiff => write!(f, "application/tiff"),
+            ImageFormat::Png => write!(f, "image/png"),
+        }
+    }
+}
+
+/// Which images OPS has for a publication - section 3.1.3 of the EPO OPS doc.
+#[derive(Debug)]
+pub struct ImageInquiry {
+    pub publication: Option<PatentDetails>,
+    pub instances: Vec<DocumentInstance>,
+}
+
+impl ImageInquiry {
+    pub fn full_document(&self) -> Option<&DocumentInstance> {
+        self.instances
+            .iter()
+            .find(|i| i.kind == ImageKind::FullDocument)
+    }
+}
+
+#[derive(Debug)]
+pub struct DocumentInstance {
+    pub kind: ImageKind,
+    /// Where the pages are fetched from, e.g. "published-data/images/EP/1000000/A1/fullimage".
+    pub link: String,
+    pub number_of_pages: u32,
+    /// The mime types the pages can be fetched as.
+    pub formats: Vec<String>,
+    pub sections: Vec<DocumentSection>,
+}
+
+/// Where a part of the document (e.g. "CLAIMS") starts.
+#[derive(Debug)]
+pub struct DocumentSection {
+    pub name: String,
+    pub start_page: u32,
+}
+
+/* Image inquiry structs
+ *  These are for deserialising the JSON from the images inquiry.
+ */
+
+#[derive(Debug, Deserialize)]
+struct ImgDocumentSection {
+    #[serde(rename(deserialize = "@name"))]
+    name: String,
+    #[serde(rename(deserialize = "@start-page"))]
+    start_page: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ImgDocumentFormatOptions {
+    #[serde(rename(deserialize = "ops:document-format"), default)]
+    ops_document_format: OneOrMany<Text>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ImgDocumentInstance {
+    #[serde(rename(deserialize = "@desc"))]
+    desc: String,
+    #[serde(rename(deserialize = "@link"))]
+    link: String,
+    #[serde(rename(deserialize = "@number-of-pages"))]
+    number_of_pages: String,
+    #[serde(rename(deserialize = "ops:document-format-options"))]
+    ops_document_format_options: Option<ImgDocumentFormatOptions>,
+    #[serde(rename(deserialize = "ops:document-section"), default)]
+    ops_document_section: OneOrMany<ImgDocumentSection>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ImgInquiryResult {
+    #[serde(rename(deserialize = "publication-reference"))]
+    publication_reference: Option<DocumentReference>,
+    #[serde(rename(deserialize = "ops:document-instance"), default)]
+    ops_document_instance: OneOrMany<ImgDocumentInstance>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ImgDocumentInquiry {
+    #[serde(rename(deserialize = "ops:inquiry-result"), default)]
+    ops_inquiry_result: OneOrMany<ImgInquiryResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ImgWorldPatentData {
+    #[serde(rename(deserialize = "ops:document-inquiry"))]
+    ops_document_inquiry: ImgDocumentInquiry,
+}
+
+#[derive(Debug, Deserialize)]
+struct ImgInquiry {
+    #[serde(rename(deserialize = "ops:world-patent-data"))]
+    ops_world_patent_data: ImgWorldPatentData,
+}
+
+fn parse_count(value: &str, what: &str) -> Result<u32, OpsError> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| OpsError::Decode(format!("Couldn't parse {} \"{}\"", what, value).into()))
+}
+
+pub(crate) fn parse_image_inquiry(response: &OpsResponse) -> Result<ImageInquiry, OpsError> {
+    let result: ImgInquiry = response.json()?;
+    let mut inquiry = ImageInquiry {
+        publication: None,
+        instances: Vec::new(),
+    };
+    for inquiry_result in result
+        .ops_world_patent_data
+        .ops_document_inquiry
+        .ops_inquiry_result
+        .into_vec()
+    {
+        if inquiry.publication.is_none() {
+            inquiry.publication = inquiry_result.publication_reference.and_then(|r| {
+                preferred_document_id(r.document_id, PatentReferenceType::Publication)
+            });
+        }
+        for instance in inquiry_result.ops_document_instance.into_vec() {
+            let mut sections = Vec::new();
+            for section in instance.ops_document_section.into_vec() {
+                sections.push(DocumentSection {
+                    start_page: parse_count(&section.start_page, "@start-page")?,
+                    name: section.name,
+                });
+            }
+            inquiry.instances.push(DocumentInstance {
+                kind: ImageKind::from(instance.desc.as_str()),
+                link: instance.link,
+                number_of_pages: parse_count(&instance.number_of_pages, "@number-of-pages")?,
+                formats: instance
+                    .ops_document_format_options
+                    .map(|o| {
+                        o.ops_document_format
+                            .into_vec()
+                            .into_iter()
+                            .map(|f| f.value)
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+                sections,
+            });
+        }
+    }
+    Ok(inquiry)
+}
+
+pub(crate) fn no_full_document(patent_details: &PatentDetails) -> OpsError {
+    OpsError::InvalidInput(format!(
+        "OPS has no full document images for {}",
+        patent_details
+    ))
+}
+
+/// Where page `page` of the full document gets cached, e.g. `<folder>/EP1000000A1/3.pdf`.
+pub(crate) fn full_document_page_path(
+    folder: &Path,
+    patent_details: &PatentDetails,
+    page: u32,
+) -> PathBuf {
+    folder
+        .join(patent_details.to_string())
+        .join(format!("{}.pdf", page))
+}
+
+pub(crate) fn full_document_path(folder: &Path, patent_details: &PatentDetails) -> PathBuf {
+    folder.join(format!("{}.pdf", patent_details))
+}
+
+fn pdf_error(e: lopdf::Error) -> OpsError {
+    OpsError::Decode(Box::new(e))
+}
+
+// Attributes a page can inherit from the page tree - they need copying onto the page itself as
+//  the page trees of the single page documents are thrown away
+const INHERITABLE_PAGE_ATTRIBUTES: [&[u8]; 4] = [b"Resources", b"MediaBox", b"CropBox", b"Rotate"];
+
+/// Joins the (single page) PDFs OPS returns for each page into one document.
+pub(crate) fn merge_pdfs(pages: &[Vec<u8>]) -> Result<Vec<u8>, OpsError> {
+    let mut merged = Document::with_version("1.5");
+    let pages_id = merged.new_object_id();
+    let mut kids: Vec<Object> = Vec::new();
+
+    for page in pages {
+        let mut document = Document::load_mem(page).map_err(pdf_error)?;
+        document.renumber_objects_with(merged.max_id + 1);
+        merged.max_id = document.max_id;
+
+        let page_ids: Vec<ObjectId> = document.get_pages().into_values().collect();
+        for page_id in &page_ids {
+            let mut inherited = Vec::new();
+            let mut parent = document
+                .get_dictionary(*page_id)
+                .and_then(|p| p.get(b"Parent"))
+                .and_then(Object::as_reference)
+                .ok();
+            while let Some(parent_id) = parent {
+                let Ok(parent_dict) = document.get_dictionary(parent_id) else {
+                    break;
+                };
+                for key in INHERITABLE_PAGE_ATTRIBUTES {
+                    if let Ok(value) = parent_dict.get(key) {
+                        inherited.push((key, value.clone()));
+                    }
+                }
+                parent = parent_dict
+                    .get(b"Parent")
+                    .and_then(Object::as_reference)
+                    .ok();
+            }
+            let page_dict = document.get_dictionary_mut(*page_id).map_err(pdf_error)?;
+            for (key, value) in inherited {
+                // The closest ancestor came first, so it wins
+                if !page_dict.has(key) {
+                    page_dict.set(key, value);
+                }
+            }
+            page_dict.set("Parent", pages_id);
+            kids.push(Object::Reference(*page_id));
+        }
+
+        for (object_id, object) in document.objects {
+            match object.type_name().unwrap_or_default() {
+                b"Catalog" | b"Pages" | b"Outlines" | b"Outline" => {}
+                _ => {
+                    merged.objects.insert(object_id, object);
+                }
+            }
+        }
+    }
+
+    let page_count = kids.len() as i64;
+    merged.objects.insert(
+        pages_id,
+        Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Kids" => kids,
+            "Count" => page_count,
+        }),
+    );
+    let catalog_id = merged.add_object(dictionary! {
+        "Type" => "Catalog",
+        "Pages" => pages_id,
+    });
+    merged.trailer.set("Root", catalog_id);
+
+    let mut pdf = Vec::new();
+    merged.save_to(&mut pdf)?;
+    Ok(pdf)
+}
+
+impl ClientCore {
+    pub(crate) fn image_inquiry_request(
+        &self,
+        patent_details: &PatentDetails,
+    ) -> Result<OpsRequest, OpsError> {
+        let mut url = reference_type_path(
+            patent_details,
+            &self.settings.endpoints.service_url("published-data"),
+        )?;
+        url.push_str("images");
+        let mut headers = HeaderMap::new();
+        headers.insert("Content-Type", "text/plain".parse().unwrap());
+        headers.insert("Accept", self.accept());
+        Ok(OpsRequest::post(
+            &url,
+            Some(patent_details.to_string()),
+            headers,
+            ServiceKind::Retrieval,
+        ))
+    }
+
+    /// The request for one page (starting at 1) of the images behind `link`, which comes from an
+    /// image inquiry.
+    pub(crate) fn image_page_request(
+        &self,
+        link: &str,
+        page: u32,
+        format: ImageFormat,
+    ) -> Result<OpsRequest, OpsError> {
+        if page == 0 {
+            return Err(OpsError::InvalidInput("Image pages start at 1".to_string()));
+        }
+        let url = format!(
+            "{}.{}?Range={}",
+            self.settings
+                .endpoints
+                .service_url(link.trim_start_matches('/')),
+            format.extension(),
+            page
+        );
+        let mut headers = HeaderMap::new();
+        headers.insert("Accept", format.to_string().parse().unwrap());
+        Ok(OpsRequest::get(&url, headers, ServiceKind::Images))
+    }
+
+    pub(crate) fn get_image_inquiry_response(
+        &self,
+        patent_details: &PatentDetails,
+    ) -> Result<Single, OpsError> {
+        Ok(Single::new(self.image_inquiry_request(patent_details)?))
+    }
+
+    pub(crate) fn get_image_inquiry(
+        &self,
+        patent_details: &PatentDetails,
+    ) -> Result<impl Exchange<Output = ImageInquiry>, OpsError> {
+        Ok(self
+            .get_image_inquiry_response(patent_details)?
+            .map(|response| parse_image_inquiry(&response)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ops_client::test_core;
+    use crate::response::fixture_response;
+
+    #[test]
+    fn test_image_inquiry() {
+        let response = fixture_response("test/example_images_inquiry.json");
+        let inquiry = parse_image_inquiry(&response).unwrap();
+        assert_eq!(inquiry.instances.len(), 3);
+        let full_document = inquiry.full_document().unwrap();
+        assert_eq!(full_document.number_of_pages, 8);
+        assert_eq!(
+            full_document.formats,
+            vec!["application/pdf", "application/tiff"]
+        );
+        assert_eq!(full_document.sections[1].name, "CLAIMS");
+        assert_eq!(full_document.sections[1].start_page, 5);
+        assert_eq!(inquiry.instances[2].kind, ImageKind::FirstPageClipping);
+
+        let core = test_core();
+        let request = core
+            .image_page_request(&full_document.link, 3, ImageFormat::Pdf)
+            .unwrap();
+        assert_eq!(
+            request.url,
+            "https://ops.epo.org/3.2/rest-services/published-data/images/EP/1000000/A1/fullimage.pdf?Range=3"
+        );
+        assert!(
+            core.image_page_request(&full_document.link, 0, ImageFormat::Pdf)
+                .is_err()
+        );
+    }
+
+    fn single_page_pdf(width: i64) -> Vec<u8> {
+        let mut document = Document::with_version("1.5");
+        let pages_id = document.new_object_id();
+        let page_id = document.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+        });
+        // MediaBox only on the page tree, to check it gets inherited
+        document.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => vec![page_id.into()],
+                "Count" => 1,
+                "MediaBox" => vec![0.into(), 0.into(), width.into(), 842.into()],
+            }),
+        );
+        let catalog_id = document.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+        });
+        document.trailer.set("Root", catalog_id);
+        let mut pdf = Vec::new();
+        document.save_to(&mut pdf).unwrap();
+        pdf
+    }
+
+    #[test]
+    fn test_merge_pdfs() {
+        let pages = vec![
+            single_page_pdf(595),
+            single_page_pdf(600),
+            single_page_pdf(605),
+        ];
+        let merged = merge_pdfs(&pages).unwrap();
+        let document = Document::load_mem(&merged).unwrap();
+        let page_ids: Vec<ObjectId> = document.get_pages().into_values().collect();
+        assert_eq!(page_ids.len(), 3);
+        // Still in order, each with its own size
+        let widths: Vec<i64> = page_ids
+            .iter()
+            .map(|id| {
+                let media_box = document
+                    .get_dictionary(*id)
+                    .unwrap()
+                    .get(b"MediaBox")
+                    .unwrap()
+                    .as_array()
+                    .unwrap();
+                media_box[2].as_i64().unwrap()
+            })
+            .collect();
+        assert_eq!(widths, vec![595, 600, 605]);
+
+        assert!(merge_pdfs(&[b"not a pdf".to_vec()]).is_err());
+    }
+}
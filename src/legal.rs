@@ -0,0 +1,189 @@
+use crate::deser::text_content;
+use crate::exchange::{Exchange, Single};
+use crate::family::parse_family;
+use crate::ops_client::{ClientCore, OpsRequest, reference_type_path};
+use crate::{OpsError, OpsResponse, PatentDetails, ServiceKind};
+use chrono::NaiveDate;
+use reqwest::header::HeaderMap;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// One INPADOC legal event - section 3.5 of the EPO OPS doc.
+///
+/// OPS reports each event as a code plus a set of numbered "L" fields. The ones with a known
+/// meaning are picked out, all of them are kept in `fields`.
+#[derive(Debug)]
+pub struct LegalEvent {
+    /// e.g. "AK" or "PG25".
+    pub code: String,
+    pub description: Option<String>,
+    /// "+" or "-", whether the event is positive or negative for the right.
+    pub influence: Option<String>,
+    /// The office that reported the event (L001EP) - "EP" for everything the EPO reports, even
+    /// when the event is about one of the designated states.
+    pub country: Option<String>,
+    /// The designated state the event is about, from the "Ref country code:" in the free text,
+    /// e.g. "GB" for the lapse (PG25) of the GB validation.
+    pub designated_state: Option<String>,
+    /// When the event was published in the gazette (L007EP).
+    pub gazette_date: Option<NaiveDate>,
+    /// From the "Effective date:" in the free text, where there is one.
+    pub effective_date: Option<NaiveDate>,
+    /// The free text fields (L500EP onwards) in order, e.g. the designated states or
+    /// "Ref country code: GB".
+    pub free_text: Vec<String>,
+    /// All of the "L" fields by name, e.g. "L001EP".
+    pub fields: BTreeMap<String, String>,
+}
+
+/* Legal structs
+ *  ops:legal elements appear in both the legal and family services.
+ */
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct OpsLegal {
+    #[serde(rename(deserialize = "@code"))]
+    code: String,
+    #[serde(rename(deserialize = "@desc"))]
+    desc: Option<String>,
+    #[serde(rename(deserialize = "@infl"))]
+    infl: Option<String>,
+    #[serde(flatten)]
+    fields: BTreeMap<String, Value>,
+}
+
+fn parse_date(date: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(date.trim(), "%Y%m%d").ok()
+}
+
+fn effective_date(free_text: &[String]) -> Option<NaiveDate> {
+    let text = free_text.join(" ");
+    let (_, after) = text.split_once("Effective date:")?;
+    parse_date(after.split_whitespace().next()?)
+}
+
+fn designated_state(free_text: &[String]) -> Option<String> {
+    let text = free_text.join(" ");
+    let (_, after) = text.split_once("Ref country code:")?;
+    Some(after.split_whitespace().next()?.to_string())
+}
+
+impl From<OpsLegal> for LegalEvent {
+    fn from(legal: OpsLegal) -> Self {
+        let fields: BTreeMap<String, String> = legal
+            .fields
+            .iter()
+            .filter_map(|(name, value)| {
+                let name = name.strip_prefix("ops:").unwrap_or(name);
+                name.starts_with('L')
+                    .then(|| (name.to_string(), text_content(value).trim().to_string()))
+            })
+            .collect();
+        // The names sort by number, so this keeps them in order
+        let free_text: Vec<String> = fields
+            .iter()
+            .filter(|(name, text)| name.as_str() >= "L500" && !text.is_empty())
+            .map(|(_, text)| text.clone())
+            .collect();
+        LegalEvent {
+            code: legal.code.trim().to_string(),
+            description: legal.desc.map(|d| d.trim().to_string()),
+            influence: legal.infl,
+            country: fields.get("L001EP").cloned(),
+            gazette_date: fields.get("L007EP").and_then(|d| parse_date(d)),
+            designated_state: designated_state(&free_text),
+            effective_date: effective_date(&free_text),
+            free_text,
+            fields,
+        }
+    }
+}
+
+/// The legal events of every publication in the response, oldest first as OPS sends them.
+pub(crate) fn parse_legal_events(response: &OpsResponse) -> Result<Vec<LegalEvent>, OpsError> {
+    Ok(parse_family(response)?
+        .members
+        .into_iter()
+        .flat_map(|member| member.legal)
+        .collect())
+}
+
+impl ClientCore {
+    pub(crate) fn legal_request(
+        &self,
+        patent_details: &PatentDetails,
+    ) -> Result<OpsRequest, OpsError> {
+        let mut url = reference_type_path(
+            patent_details,
+            &self.settings.endpoints.service_url("legal"),
+        )?;
+        url.pop();
+        let mut headers = HeaderMap::new();
+        headers.insert("Content-Type", "text/plain".parse().unwrap());
+        headers.insert("Accept", self.accept());
+        Ok(OpsRequest::post(
+            &url,
+            Some(patent_details.to_string()),
+            headers,
+            ServiceKind::Inpadoc,
+        ))
+    }
+
+    pub(crate) fn get_legal_events_response(
+        &self,
+        patent_details: &PatentDetails,
+    ) -> Result<Single, OpsError> {
+        Ok(Single::new(self.legal_request(patent_details)?))
+    }
+
+    pub(crate) fn get_legal_events(
+        &self,
+        patent_details: &PatentDetails,
+    ) -> Result<impl Exchange<Output = Vec<LegalEvent>>, OpsError> {
+        Ok(self
+            .get_legal_events_response(patent_details)?
+            .map(|response| parse_legal_events(&response)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::response::fixture_response;
+
+    #[test]
+    fn test_legal_deser() {
+        let response = fixture_response("test/example_legal.json");
+        let events = parse_legal_events(&response).unwrap();
+        assert_eq!(events.len(), 3);
+
+        let designation = &events[0];
+        assert_eq!(designation.code, "AK");
+        assert_eq!(
+            designation.description.as_deref(),
+            Some("DESIGNATED CONTRACTING STATES:")
+        );
+        assert_eq!(designation.influence.as_deref(), Some("+"));
+        assert_eq!(designation.country.as_deref(), Some("EP"));
+        assert_eq!(
+            designation.gazette_date,
+            NaiveDate::from_ymd_opt(2000, 5, 17)
+        );
+        assert_eq!(designation.free_text, vec!["AT BE CH CY DE"]);
+        assert_eq!(designation.effective_date, None);
+        assert_eq!(designation.designated_state, None);
+
+        let lapse = &events[2];
+        assert_eq!(lapse.code, "PG25");
+        assert_eq!(lapse.influence.as_deref(), Some("-"));
+        assert_eq!(
+            lapse.free_text,
+            vec!["Ref country code: GB", "Effective date: 20031108"]
+        );
+        assert_eq!(lapse.effective_date, NaiveDate::from_ymd_opt(2003, 11, 8));
+        assert_eq!(lapse.country.as_deref(), Some("EP"));
+        assert_eq!(lapse.designated_state.as_deref(), Some("GB"));
+        assert_eq!(lapse.fields["L002EP"], "1000000");
+    }
+}
@@ -1,12 +1,86 @@
+#[cfg(not(any(feature = "blocking", feature = "async")))]
+compile_error!("At least one of the \"blocking\" and \"async\" features needs to be enabled");
+
+mod abstracts;
+pub use crate::abstracts::Abstract;
+
+mod auth;
+mod error;
+pub use crate::error::OpsError;
+
+mod exchange;
+
 mod ops_client;
+#[cfg(feature = "blocking")]
+pub use crate::ops_client::OpsClient;
 pub use crate::ops_client::{
-    PatentDetails, PatentReferenceType, PublicationConstituents, RegisterConstituents,
-    get_auth_token, get_publication, get_publication_bulk, get_register_info, get_usage_data,
-    search_register,
+    Endpoints, HttpSettings, OpsClientSettings, PatentDetails, PatentReferenceType, ProxySettings,
+    PublicationConstituents, RegisterConstituents,
+};
+
+#[cfg(feature = "async")]
+mod async_client;
+#[cfg(feature = "async")]
+pub use crate::async_client::AsyncOpsClient;
+
+mod bulk;
+pub use crate::bulk::{BulkFailure, BulkResults, MAX_BULK_NUMBERS};
+
+mod classification;
+pub use crate::classification::{
+    ClassificationMapping, ClassificationScheme, CpcClassification, CpcDepth, CpcItem,
+    CpcSearchResult,
 };
 
 mod config;
-pub use crate::config::{EpoOpsCredentials, get_cache_folder, get_epo_credentials, load_config};
+pub use crate::config::{Config, EpoOpsCredentials, load_config};
+
+mod equivalents;
+pub use crate::equivalents::{Equivalent, EquivalentsConstituents};
+
+mod family;
+pub use crate::family::{Family, FamilyConstituents, FamilyMember};
+
+mod fulltext;
+pub use crate::fulltext::{
+    Claim, Claims, Description, FulltextInquiry, FulltextInstance, Paragraph,
+};
+
+mod fullcycle;
+pub use crate::fullcycle::{FullCycle, PublicationStage};
+
+mod images;
+pub use crate::images::{DocumentInstance, DocumentSection, ImageFormat, ImageInquiry, ImageKind};
+
+mod legal;
+pub use crate::legal::LegalEvent;
+
+mod number;
+pub use crate::number::NumberFormat;
+
+mod response;
+pub use crate::response::{OpsResponse, ResponseFormat};
+
+mod search;
+pub use crate::search::{
+    MAX_PUBLISHED_SEARCH_RESULTS, PublishedSearchResult, PublishedSearchResults,
+};
+
+mod quota;
+pub use crate::quota::{DEFAULT_WEEKLY_QUOTA, QuotaGuard, QuotaStatus};
+
+mod retry;
+pub use crate::retry::RetryPolicy;
+
+mod throttling;
+pub use crate::throttling::{
+    ServiceKind, ServiceThrottle, SystemState, ThrottlingControl, TrafficLight,
+};
+
+mod usage;
+pub use crate::usage::{QuotaProjection, UsageCounts, UsageEntry, UsageReport};
+
+mod xml;
 
 mod deser;
 pub use crate::deser::{
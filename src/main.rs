@@ -1,49 +1,57 @@
 use chrono::NaiveDate;
 use epo_ops_testing::{
-    PatentDetails, PatentReferenceType, PublicationConstituents, RegApplicationReferenceOneOrMany,
-    RegOpsRegisterResult, RegSearchOpsSearchResults, RegisterConstituents, get_cache_folder,
-    get_publication, get_publication_bulk, get_register_info, get_usage_data, load_config,
-    search_register,
+    Config, OpsClient, PatentDetails, PatentReferenceType, PublicationConstituents,
+    RegApplicationReferenceOneOrMany, RegOpsRegisterResult, RegSearchOpsSearchResults,
+    RegisterConstituents, load_config,
 };
 use glob::glob;
+use serde_json::{Value, json};
 use std::fs;
 use std::io::BufReader;
 use std::path::Path;
 
 fn main() {
     env_logger::init();
-    load_config("conf.ini");
-    /*
-    let info = get_usage_data(
-        NaiveDate::from_ymd_opt(2025, 10, 20).unwrap(),
-        NaiveDate::from_ymd_opt(2025, 11, 4).unwrap(),
-    );
-    println!("{:#?}", info);
-     */
-    //search_ibm();
-    //get_all_ibm_register_info();
-    get_all_ibm_ep_filing_dates();
+    let config = load_config("conf.ini");
+    get_all_ibm_ep_filing_dates(&config);
 }
 
 #[allow(dead_code)]
-fn search_ibm() {
-    let cache_folder = get_cache_folder();
+fn search_ibm(config: &Config, client: &OpsClient) {
+    let cache_folder = config.cache_folder();
     let query_string = "pa=ibm";
-    let infos = search_register(query_string);
+    let infos = client
+        .search_register(query_string)
+        .expect("Couldn't search the register");
     println!("Searched register for ibm");
     for (i, info) in infos.iter().enumerate() {
         let filename = format!(
             "{}/ibm_space/pa.ibm.{}.register.search.json",
             cache_folder, i
         );
-        fs::write(&filename, info)
+        fs::write(&filename, &info.body)
             .unwrap_or_else(|_| panic!("Should be able to write to {}", filename));
     }
 }
 
 #[allow(dead_code)]
-fn get_all_ibm_ep_filing_dates() {
-    let cache_folder = get_cache_folder();
+fn download_pdf(config: &Config, client: &OpsClient) {
+    let patent_details = PatentDetails {
+        country_code: "EP".to_string(),
+        number: "1000000".to_string(),
+        kind_code: Some("A1".to_string()),
+        date: None,
+        reference_type: PatentReferenceType::Publication,
+    };
+    let path = client
+        .download_full_document(&patent_details, Path::new(&config.cache_folder()))
+        .expect("Couldn't download the full document");
+    println!("Wrote {}", path.display());
+}
+
+#[allow(dead_code)]
+fn get_all_ibm_ep_filing_dates(config: &Config) {
+    let cache_folder = config.cache_folder();
     let ibm_reg_search_result_files = glob(&format!("{}/ibm_space/*.register.json", cache_folder))
         .expect("Failed to read glob pattern");
     let mut i = 0;
@@ -76,9 +84,9 @@ fn get_all_ibm_ep_filing_dates() {
 }
 
 #[allow(dead_code)]
-fn get_all_ibm_register_info() {
+fn get_all_ibm_register_info(config: &Config, client: &OpsClient) {
     println!("Hello");
-    let cache_folder = get_cache_folder();
+    let cache_folder = config.cache_folder();
     let ibm_reg_search_result_files = glob(&format!(
         "{}/ibm_space/pa.ibm.*.register.search.json",
         cache_folder
@@ -93,6 +101,7 @@ fn get_all_ibm_register_info() {
                 let reader = BufReader::new(file);
                 let json: RegSearchOpsSearchResults =
                     serde_json::from_reader(reader).expect("JSON was not well formatted");
+                let mut to_fetch = Vec::new();
                 for register_document in json
                     .ops_world_patent_data
                     .ops_register_search
@@ -109,12 +118,39 @@ fn get_all_ibm_register_info() {
                     };
                     let filename =
                         format!("{}/ibm_space/{}.register.json", cache_folder, pat_details);
-                    if Path::new(&filename).exists() {
+                    if !Path::new(&filename).exists() {
+                        to_fetch.push(pat_details);
+                    }
+                }
+                let bulk_info = match client
+                    .get_register_info_bulk(&to_fetch, &[RegisterConstituents::Biblio])
+                {
+                    Ok(bulk_info) => bulk_info,
+                    Err(e) => {
+                        println!("Couldn't get register info: {}", e);
                         continue;
                     }
-                    let bulk_info =
-                        get_register_info(&pat_details, &vec![RegisterConstituents::Biblio]);
-                    fs::write(&filename, bulk_info)
+                };
+                for failure in &bulk_info.failures {
+                    println!(
+                        "Couldn't get register info for {}: {}",
+                        failure.patent_details, failure.error
+                    );
+                }
+                for (pat_details, register_document) in &bulk_info.results {
+                    let filename =
+                        format!("{}/ibm_space/{}.register.json", cache_folder, pat_details);
+                    // Same layout as a single register retrieval, so it reads back as a RegOpsRegisterResult
+                    let info = json!({
+                        "ops:world-patent-data": {
+                            "ops:register-search": {
+                                "reg:register-documents": {
+                                    "reg:register-document": register_document
+                                }
+                            }
+                        }
+                    });
+                    fs::write(&filename, info.to_string())
                         .unwrap_or_else(|_| panic!("Should be able to write to {}", filename));
                 }
             }
@@ -123,8 +159,8 @@ fn get_all_ibm_register_info() {
 }
 
 #[allow(dead_code)]
-fn example_getting_patent_details() {
-    let cache_folder = get_cache_folder();
+fn example_getting_patent_details(config: &Config, client: &OpsClient) {
+    let cache_folder = config.cache_folder();
 
     let constituents = vec![
         RegisterConstituents::Biblio,
@@ -139,12 +175,14 @@ fn example_getting_patent_details() {
         date: None,
         reference_type: PatentReferenceType::Publication,
     };
-    let info = get_register_info(&first_patent_details, &constituents);
+    let info = client
+        .get_register_info(&first_patent_details, &constituents)
+        .expect("Couldn't get register info");
     let filename = format!(
         "{}/{}.register.biblio.json",
         cache_folder, first_patent_details
     );
-    fs::write(&filename, info)
+    fs::write(&filename, &info.body)
         .unwrap_or_else(|_| panic!("Should be able to write to {}", filename));
 
     let second_patent_details = PatentDetails {
@@ -154,13 +192,15 @@ fn example_getting_patent_details() {
         date: None,
         reference_type: PatentReferenceType::Application,
     };
-    let info = get_register_info(&second_patent_details, &constituents);
+    let info = client
+        .get_register_info(&second_patent_details, &constituents)
+        .expect("Couldn't get register info");
     let filename = format!(
         "{}/{}.register.biblio.json",
         cache_folder, second_patent_details
     );
     println!("Got second patent");
-    fs::write(&filename, info)
+    fs::write(&filename, &info.body)
         .unwrap_or_else(|_| panic!("Should be able to write to {}", filename));
 
     let constituents = vec![
@@ -177,27 +217,33 @@ fn example_getting_patent_details() {
         date: None,
         reference_type: PatentReferenceType::Application,
     };
-    let info = get_register_info(&third_patent_details, &constituents);
+    let info = client
+        .get_register_info(&third_patent_details, &constituents)
+        .expect("Couldn't get register info");
     let filename = format!(
         "{}/{}.register.upp.json",
         cache_folder, third_patent_details
     );
     println!("Got third patent");
-    fs::write(&filename, info)
+    fs::write(&filename, &info.body)
         .unwrap_or_else(|_| panic!("Should be able to write to {}", filename));
 
-    let info = get_usage_data(
-        NaiveDate::from_ymd_opt(2025, 10, 10).unwrap(),
-        NaiveDate::from_ymd_opt(2025, 10, 20).unwrap(),
-    );
+    let info = client
+        .get_usage_data(
+            NaiveDate::from_ymd_opt(2025, 10, 10).unwrap(),
+            NaiveDate::from_ymd_opt(2025, 10, 20).unwrap(),
+        )
+        .expect("Couldn't get usage data");
     println!("{:#?}", info);
 
     let query_string = "pa=nchain";
-    let infos = search_register(query_string);
+    let infos = client
+        .search_register(query_string)
+        .expect("Couldn't search the register");
     println!("Searched register for nchain");
     for (i, info) in infos.iter().enumerate() {
         let filename = format!("{}/pa.nchain.{}.register.search.json", cache_folder, i);
-        fs::write(&filename, info)
+        fs::write(&filename, &info.body)
             .unwrap_or_else(|_| panic!("Should be able to write to {}", filename));
     }
 
@@ -206,13 +252,15 @@ fn example_getting_patent_details() {
         PublicationConstituents::Abstract,
         PublicationConstituents::FullCycle,
     ];
-    let info = get_publication(&third_patent_details, &constituents);
+    let info = client
+        .get_publication(&third_patent_details, &constituents)
+        .expect("Couldn't get publication");
     let filename = format!(
         "{}/{}.publication.all.json",
         cache_folder, third_patent_details
     );
     println!("Got third patent again");
-    fs::write(&filename, info)
+    fs::write(&filename, &info.body)
         .unwrap_or_else(|_| panic!("Should be able to write to {}", filename));
 
     let all_patent_details = vec![
@@ -220,9 +268,27 @@ fn example_getting_patent_details() {
         second_patent_details,
         third_patent_details,
     ];
-    let info = get_publication_bulk(&all_patent_details, &constituents);
-    println!("Got two patents");
+    let bulk_info = client
+        .get_publication_bulk(&all_patent_details, &constituents)
+        .expect("Couldn't get publications");
+    println!("Got {} patents", bulk_info.results.len());
+    for failure in &bulk_info.failures {
+        println!("Couldn't get {}: {}", failure.patent_details, failure.error);
+    }
+    let exchange_documents: Vec<&Value> = bulk_info
+        .results
+        .iter()
+        .flat_map(|(_, exchange_documents)| exchange_documents)
+        .collect();
+    // Same layout as a single retrieval
+    let info = json!({
+        "ops:world-patent-data": {
+            "exchange-documents": {
+                "exchange-document": exchange_documents
+            }
+        }
+    });
     let filename = format!("{}/multiple.publication.all.json", cache_folder);
-    fs::write(&filename, info)
+    fs::write(&filename, info.to_string())
         .unwrap_or_else(|_| panic!("Should be able to write to {}", filename));
 }
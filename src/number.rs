@@ -0,0 +1,192 @@
+use crate::deser::{DocumentReference, preferred_document_id};
+use crate::exchange::{Exchange, Single};
+use crate::ops_client::{ClientCore, OpsRequest, reference_type_name};
+use crate::{OpsError, OpsResponse, PatentDetails, PatentReferenceType, ServiceKind};
+use reqwest::header::HeaderMap;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::fmt;
+
+/// The ways OPS writes patent numbers - section 3.3 of the EPO OPS doc.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NumberFormat {
+    /// As the number was filed/published, e.g. `JP.(2006-147056).A.20060526`.
+    Original,
+    /// e.g. `EP.1000000.A1.20000517`.
+    Docdb,
+    /// e.g. `EP1000000A1.20000517` - what `PatentDetails` displays as.
+    Epodoc,
+}
+
+impl fmt::Display for NumberFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NumberFormat::Original => write!(f, "original"),
+            NumberFormat::Docdb => write!(f, "docdb"),
+            NumberFormat::Epodoc => write!(f, "epodoc"),
+        }
+    }
+}
+
+impl PatentDetails {
+    /// The number written out in `format`, as the number-service expects it.
+    pub fn format_as(&self, format: NumberFormat) -> String {
+        let number = match format {
+            NumberFormat::Epodoc => return self.to_string(),
+            NumberFormat::Docdb => format!("{}.{}", self.country_code, self.number),
+            // The brackets let the number itself contain '.'s
+            NumberFormat::Original => format!("{}.({})", self.country_code, self.number),
+        };
+        let mut parts = vec![number];
+        if let Some(kind_code) = &self.kind_code {
+            parts.push(kind_code.clone());
+        }
+        if let Some(date) = &self.date {
+            parts.push(date.format("%Y%m%d").to_string());
+        }
+        parts.join(".")
+    }
+}
+
+/* Number service structs
+ *  These are for deserialising the JSON from the number-service. The output holds a single
+ *  application-reference, publication-reference or priority-claim depending on what was converted.
+ */
+
+#[derive(Debug, Deserialize)]
+struct NumStandardization {
+    #[serde(rename(deserialize = "ops:output"))]
+    ops_output: Map<String, Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NumWorldPatentData {
+    #[serde(rename(deserialize = "ops:standardization"))]
+    ops_standardization: NumStandardization,
+}
+
+#[derive(Debug, Deserialize)]
+struct NumResult {
+    #[serde(rename(deserialize = "ops:world-patent-data"))]
+    ops_world_patent_data: NumWorldPatentData,
+}
+
+pub(crate) fn parse_number(
+    response: &OpsResponse,
+    reference_type: PatentReferenceType,
+) -> Result<PatentDetails, OpsError> {
+    let result: NumResult = response.json()?;
+    let reference = result
+        .ops_world_patent_data
+        .ops_standardization
+        .ops_output
+        .into_iter()
+        .find(|(key, _)| !key.starts_with('@'))
+        .map(|(_, reference)| reference)
+        .ok_or_else(|| OpsError::Decode("No reference in the number-service output".into()))?;
+    let reference: DocumentReference = serde_json::from_value(reference)?;
+    preferred_document_id(reference.document_id, reference_type)
+        .ok_or_else(|| OpsError::Decode("No document-id in the number-service output".into()))
+}
+
+impl ClientCore {
+    pub(crate) fn number_request(
+        &self,
+        patent_details: &PatentDetails,
+        input_format: NumberFormat,
+        output_format: NumberFormat,
+    ) -> Result<OpsRequest, OpsError> {
+        let url = format!(
+            "{}/{}/{}/{}",
+            self.settings.endpoints.service_url("number-service"),
+            reference_type_name(patent_details)?,
+            input_format,
+            output_format
+        );
+        let mut headers = HeaderMap::new();
+        headers.insert("Content-Type", "text/plain".parse().unwrap());
+        headers.insert("Accept", self.accept());
+        Ok(OpsRequest::post(
+            &url,
+            Some(patent_details.format_as(input_format)),
+            headers,
+            ServiceKind::Other,
+        ))
+    }
+
+    pub(crate) fn convert_number_response(
+        &self,
+        patent_details: &PatentDetails,
+        input_format: NumberFormat,
+        output_format: NumberFormat,
+    ) -> Result<Single, OpsError> {
+        Ok(Single::new(self.number_request(
+            patent_details,
+            input_format,
+            output_format,
+        )?))
+    }
+
+    pub(crate) fn convert_number(
+        &self,
+        patent_details: &PatentDetails,
+        input_format: NumberFormat,
+        output_format: NumberFormat,
+    ) -> Result<impl Exchange<Output = PatentDetails>, OpsError> {
+        let reference_type = patent_details.reference_type.clone();
+        Ok(self
+            .convert_number_response(patent_details, input_format, output_format)?
+            .map(|response| parse_number(&response, reference_type)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::response::fixture_response;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_number_formats() {
+        let mut patent_details = PatentDetails {
+            country_code: "JP".to_string(),
+            number: "2006-147056".to_string(),
+            kind_code: Some("A".to_string()),
+            date: NaiveDate::from_ymd_opt(2006, 5, 26),
+            reference_type: PatentReferenceType::Application,
+        };
+        assert_eq!(
+            patent_details.format_as(NumberFormat::Original),
+            "JP.(2006-147056).A.20060526"
+        );
+        patent_details.number = "2006147056".to_string();
+        assert_eq!(
+            patent_details.format_as(NumberFormat::Docdb),
+            "JP.2006147056.A.20060526"
+        );
+        assert_eq!(
+            patent_details.format_as(NumberFormat::Epodoc),
+            "JP2006147056A.20060526"
+        );
+        patent_details.kind_code = None;
+        patent_details.date = None;
+        assert_eq!(
+            patent_details.format_as(NumberFormat::Docdb),
+            "JP.2006147056"
+        );
+    }
+
+    #[test]
+    fn test_number_deser() {
+        let response = fixture_response("test/example_number_service.json");
+        let patent_details = parse_number(&response, PatentReferenceType::Application).unwrap();
+        assert_eq!(patent_details.country_code, "JP");
+        assert_eq!(patent_details.number, "2006147056");
+        assert_eq!(patent_details.kind_code.as_deref(), Some("A"));
+        assert_eq!(patent_details.date, NaiveDate::from_ymd_opt(2006, 5, 26));
+        assert_eq!(
+            patent_details.reference_type,
+            PatentReferenceType::Application
+        );
+    }
+}
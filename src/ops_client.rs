@@ -1,120 +1,443 @@
-use crate::{EpoOpsCredentials, get_epo_credentials};
-use crate::{TokenResponse, Usage};
+use crate::EpoOpsCredentials;
+use crate::OpsError;
+#[cfg(feature = "blocking")]
+use crate::abstracts::Abstract;
+use crate::auth::TokenManager;
+use crate::bulk::{Bulk, BulkResults, bulk_body, check_bulk_numbers};
+#[cfg(feature = "blocking")]
+use crate::classification::{
+    ClassificationMapping, ClassificationScheme, CpcClassification, CpcDepth, CpcSearchResult,
+};
+#[cfg(feature = "blocking")]
+use crate::equivalents::{Equivalent, EquivalentsConstituents};
+use crate::exchange::{Exchange, Pages, Single};
+#[cfg(feature = "blocking")]
+use crate::family::{Family, FamilyConstituents};
+#[cfg(feature = "blocking")]
+use crate::fullcycle::FullCycle;
+#[cfg(feature = "blocking")]
+use crate::fulltext::{Claims, Description, FulltextInquiry};
+#[cfg(feature = "blocking")]
+use crate::images::{
+    ImageFormat, ImageInquiry, full_document_page_path, full_document_path, merge_pdfs,
+    no_full_document,
+};
+#[cfg(feature = "blocking")]
+use crate::legal::LegalEvent;
+#[cfg(feature = "blocking")]
+use crate::number::NumberFormat;
+use crate::quota::{QuotaGuard, QuotaStatus, QuotaTracker};
+use crate::retry::RetryPolicy;
+#[cfg(feature = "blocking")]
+use crate::search::PublishedSearchResults;
+use crate::throttling::{ServiceKind, Throttler};
+#[cfg(feature = "blocking")]
+use crate::usage::UsageReport;
+use crate::{OpsResponse, ResponseFormat, TokenResponse, Usage};
 use chrono::naive::NaiveDate;
-use chrono::prelude::*;
 use log::{debug, error};
-use reqwest::StatusCode;
-use reqwest::header::HeaderMap;
+use reqwest::Method;
+use reqwest::header::{HeaderMap, HeaderValue};
 use serde_json::Value;
 use std::fmt;
+#[cfg(feature = "blocking")]
+use std::fs;
+#[cfg(feature = "blocking")]
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+#[cfg(feature = "blocking")]
 use std::sync::Mutex;
+#[cfg(feature = "blocking")]
+use std::thread;
+use std::time::{Duration, Instant};
 
-static TOKEN: Mutex<TokenMutex> = Mutex::new(new_token_mutex());
+/// Where OPS lives. Every service URL is built from these, so pointing `base_url` somewhere else
+/// (e.g. a local stand-in server for tests) moves all of them.
+#[derive(Clone, Debug)]
+pub struct Endpoints {
+    pub base_url: String,
+    pub api_version: String,
+    /// Plain http base URLs are refused unless this is set - credentials and tokens are sent
+    /// with every request.
+    pub allow_insecure_http: bool,
+}
 
-enum NeedsAuth {
-    Yes,
-    No,
+impl Default for Endpoints {
+    fn default() -> Self {
+        Endpoints {
+            base_url: "https://ops.epo.org".to_string(),
+            api_version: "3.2".to_string(),
+            allow_insecure_http: false,
+        }
+    }
 }
 
-fn do_get_request(url: &str, mut headers: HeaderMap, needs_auth: NeedsAuth) -> String {
-    match needs_auth {
-        NeedsAuth::Yes => {
-            let epo_credentials = get_epo_credentials();
-            let auth_token = get_auth_token(epo_credentials);
-            headers.insert(
-                "Authorization",
-                format!("Bearer {}", auth_token).parse().unwrap(),
-            );
+impl Endpoints {
+    pub fn validate(&self) -> Result<(), OpsError> {
+        let url = reqwest::Url::parse(&self.base_url).map_err(|e| {
+            OpsError::InvalidConfig(format!("Invalid base URL {}: {}", self.base_url, e))
+        })?;
+        match url.scheme() {
+            "https" => Ok(()),
+            "http" if self.allow_insecure_http => Ok(()),
+            "http" => Err(OpsError::InvalidConfig(format!(
+                "Refusing to use plain http base URL {} - set allow_insecure_http if you really mean it",
+                self.base_url
+            ))),
+            scheme => Err(OpsError::InvalidConfig(format!(
+                "Unsupported scheme {} in base URL {}",
+                scheme, self.base_url
+            ))),
+        }
+    }
+
+    fn root(&self) -> String {
+        format!(
+            "{}/{}",
+            self.base_url.trim_end_matches('/'),
+            self.api_version
+        )
+    }
+
+    /// The URL of a service under rest-services, e.g. `service_url("register")`.
+    pub fn service_url(&self, service: &str) -> String {
+        format!("{}/rest-services/{}", self.root(), service)
+    }
+
+    pub fn auth_url(&self) -> String {
+        format!("{}/auth/accesstoken", self.root())
+    }
+
+    pub fn usage_url(&self) -> String {
+        format!("{}/developers/me/stats/usage", self.root())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ProxySettings {
+    pub url: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+/// How the underlying HTTP client connects. One HTTP client is kept per `OpsClient`, so
+/// connections get reused between requests.
+#[derive(Clone, Debug)]
+pub struct HttpSettings {
+    pub connect_timeout: Option<Duration>,
+    /// For the blocking client this covers the whole request, for the async client the time
+    /// between reads.
+    pub read_timeout: Option<Duration>,
+    pub proxy: Option<ProxySettings>,
+    pub user_agent: String,
+    pub gzip: bool,
+}
+
+impl Default for HttpSettings {
+    fn default() -> Self {
+        HttpSettings {
+            connect_timeout: Some(Duration::from_secs(10)),
+            read_timeout: Some(Duration::from_secs(60)),
+            proxy: None,
+            user_agent: concat!("epo_ops_testing/", env!("CARGO_PKG_VERSION")).to_string(),
+            gzip: true,
         }
-        NeedsAuth::No => {}
-    }
-    let http_client = reqwest::blocking::ClientBuilder::new()
-        .redirect(reqwest::redirect::Policy::none())
-        .build()
-        .expect("Client should build");
-
-    let response = http_client.get(url).headers(headers).send();
-    handle_request_errors(response)
-}
-
-fn do_post_request(
-    url: &str,
-    data: Option<String>,
-    mut headers: HeaderMap,
-    needs_auth: NeedsAuth,
-) -> String {
-    match needs_auth {
-        NeedsAuth::Yes => {
-            let epo_credentials = get_epo_credentials();
-            let auth_token = get_auth_token(epo_credentials);
-            headers.insert(
-                "Authorization",
-                format!("Bearer {}", auth_token).parse().unwrap(),
+    }
+}
+
+impl HttpSettings {
+    pub(crate) fn reqwest_proxy(&self) -> Result<Option<reqwest::Proxy>, OpsError> {
+        let Some(proxy_settings) = &self.proxy else {
+            return Ok(None);
+        };
+        let mut proxy = reqwest::Proxy::all(&proxy_settings.url).map_err(|e| {
+            OpsError::InvalidConfig(format!("Invalid proxy URL {}: {}", proxy_settings.url, e))
+        })?;
+        if let Some(username) = &proxy_settings.username {
+            proxy = proxy.basic_auth(
+                username,
+                proxy_settings.password.as_deref().unwrap_or_default(),
             );
         }
-        NeedsAuth::No => {}
-    }
-    let http_client = reqwest::blocking::ClientBuilder::new()
-        .redirect(reqwest::redirect::Policy::none())
-        .build()
-        .expect("Client should build");
-
-    let mut response = http_client.post(url).headers(headers);
-    if let Some(d) = data {
-        response = response.body(d);
-    }
-    let response = response.send();
-    handle_request_errors(response)
-}
-
-fn handle_request_errors(response: Result<reqwest::blocking::Response, reqwest::Error>) -> String {
-    // Rather unhappy with alllll of this. I need to learn how to better reason about
-    //  and work with errors etc - even just to make it look better.
-    //  And obviously make these not panics if/when I implement good errors
-    match response {
-        Ok(resp) => match resp.status() {
-            StatusCode::OK => resp
-                .text()
-                .expect("Error decoding body - very odd - weird charset? missing body?"),
-            _ => {
-                dbg!(&resp);
-                dbg!(resp.text().unwrap());
-                panic!("Received error in response - can't go forward");
-            }
-        },
-        Err(e) => {
-            dbg!(e);
-            todo!("Error sending packet. Internet issues?");
+        Ok(Some(proxy))
+    }
+}
+
+/// Everything about an `OpsClient` other than who it talks to OPS as.
+#[derive(Clone, Debug)]
+pub struct OpsClientSettings {
+    pub endpoints: Endpoints,
+    pub http: HttpSettings,
+    /// How long before its expiry an access token gets replaced.
+    pub token_refresh_margin: Duration,
+    /// Whether to space out requests according to the `X-Throttling-Control` header OPS sends.
+    pub pace_requests: bool,
+    /// Refuse to start new requests once this much of the weekly fair use quota is used.
+    pub quota_guard: Option<QuotaGuard>,
+    pub retry_policy: RetryPolicy,
+    /// What the data services are asked to answer with - see `with_response_format` to change it
+    /// for a few requests.
+    pub response_format: ResponseFormat,
+}
+
+impl Default for OpsClientSettings {
+    fn default() -> Self {
+        OpsClientSettings {
+            endpoints: Endpoints::default(),
+            http: HttpSettings::default(),
+            token_refresh_margin: Duration::from_secs(60),
+            pace_requests: true,
+            quota_guard: None,
+            retry_policy: RetryPolicy::default(),
+            response_format: ResponseFormat::default(),
+        }
+    }
+}
+
+pub(crate) enum NeedsAuth {
+    Yes,
+    No,
+}
+
+/// A request to OPS, before authentication is added.
+pub(crate) struct OpsRequest {
+    pub(crate) method: Method,
+    pub(crate) url: String,
+    pub(crate) headers: HeaderMap,
+    pub(crate) body: Option<String>,
+    pub(crate) needs_auth: NeedsAuth,
+    // None for requests that aren't throttled, i.e. authentication
+    pub(crate) service: Option<ServiceKind>,
+}
+
+impl OpsRequest {
+    pub(crate) fn get(url: &str, headers: HeaderMap, service: ServiceKind) -> OpsRequest {
+        OpsRequest {
+            method: Method::GET,
+            url: url.to_string(),
+            headers,
+            body: None,
+            needs_auth: NeedsAuth::Yes,
+            service: Some(service),
+        }
+    }
+
+    pub(crate) fn post(
+        url: &str,
+        data: Option<String>,
+        headers: HeaderMap,
+        service: ServiceKind,
+    ) -> OpsRequest {
+        OpsRequest {
+            method: Method::POST,
+            url: url.to_string(),
+            headers,
+            body: data,
+            needs_auth: NeedsAuth::Yes,
+            service: Some(service),
         }
     }
 }
 
-#[derive(Debug)]
-struct TokenMutex {
-    access_token: String, // Would like to use a type alias here - not sure how though
-    issued_at: i64,
-    expires_in: u32,
+/// The reference type as it appears in the URLs, e.g. "publication".
+pub(crate) fn reference_type_name(
+    patent_details: &PatentDetails,
+) -> Result<&'static str, OpsError> {
+    match patent_details.reference_type {
+        PatentReferenceType::Publication => Ok("publication"),
+        PatentReferenceType::Application => Ok("application"),
+        PatentReferenceType::Priority => Ok("priority"),
+        PatentReferenceType::Unknown => Err(OpsError::InvalidInput(format!(
+            "Need to know whether {} is a publication, application or priority number",
+            patent_details
+        ))),
+    }
 }
 
-const fn new_token_mutex() -> TokenMutex {
-    TokenMutex {
-        access_token: String::new(),
-        issued_at: 0,
-        expires_in: 0,
+/// Checks the number is one the services that only take publication and application numbers -
+/// the register, full text, images and legal - can take.
+pub(crate) fn check_publication_or_application(
+    patent_details: &PatentDetails,
+) -> Result<(), OpsError> {
+    match patent_details.reference_type {
+        PatentReferenceType::Publication | PatentReferenceType::Application => Ok(()),
+        _ => Err(OpsError::InvalidInput(format!(
+            "Only supporting publication and application numbers, got {:?} for {}",
+            patent_details.reference_type, patent_details
+        ))),
     }
 }
 
-pub fn get_auth_token(epo_credentials: EpoOpsCredentials) -> String {
-    let mut token_mutex = TOKEN.lock().unwrap();
-    let current_time = Utc::now().timestamp();
-    let current_token_expired =
-        (current_time - 60) > (token_mutex.issued_at + token_mutex.issued_at);
+/// `{base_url}/{reference type}/epodoc/`, for the services that only take publication and
+/// application numbers.
+pub(crate) fn reference_type_path(
+    patent_details: &PatentDetails,
+    base_url: &str,
+) -> Result<String, OpsError> {
+    check_publication_or_application(patent_details)?;
+    any_reference_type_path(patent_details, base_url)
+}
 
-    if current_token_expired {
-        debug!(target: "authentication", "Need new authentication token");
-        let formatted_credentials = epo_credentials.format_credentials();
+/// Like `reference_type_path`, for the services that also take priority numbers - published
+/// data, equivalents and family.
+pub(crate) fn any_reference_type_path(
+    patent_details: &PatentDetails,
+    base_url: &str,
+) -> Result<String, OpsError> {
+    Ok(format!(
+        "{}/{}/epodoc/",
+        base_url,
+        reference_type_name(patent_details)?
+    ))
+}
+
+pub(crate) fn push_constituents<T: fmt::Display>(url: &mut String, constituents: &[T], default: T) {
+    if constituents.is_empty() {
+        url.push_str(&default.to_string());
+    } else {
+        for constituent in constituents {
+            url.push_str(&constituent.to_string());
+            url.push(',');
+        }
+        url.pop();
+    }
+}
+
+/// The parts of a client that don't care whether requests are sent blocking or async: the
+/// settings, the token/throttling/quota state, how each request is built and how each response is
+/// read.
+///
+/// The token, throttling and quota state are shared between the copies made for other response
+/// formats, as they all talk to OPS as the same user.
+#[derive(Clone)]
+pub(crate) struct ClientCore {
+    credentials: EpoOpsCredentials,
+    pub(crate) settings: OpsClientSettings,
+    pub(crate) token: Arc<TokenManager>,
+    throttler: Arc<Throttler>,
+    quota: Arc<QuotaTracker>,
+}
+
+impl ClientCore {
+    pub(crate) fn new(
+        credentials: EpoOpsCredentials,
+        settings: OpsClientSettings,
+    ) -> Result<ClientCore, OpsError> {
+        settings.endpoints.validate()?;
+        Ok(ClientCore {
+            credentials,
+            token: Arc::new(TokenManager::new(settings.token_refresh_margin)),
+            throttler: Arc::default(),
+            quota: Arc::default(),
+            settings,
+        })
+    }
+
+    pub(crate) fn quota_status(&self) -> QuotaStatus {
+        self.quota.status()
+    }
+
+    pub(crate) fn with_response_format(&self, response_format: ResponseFormat) -> ClientCore {
+        let mut core = self.clone();
+        core.settings.response_format = response_format;
+        core
+    }
+
+    /// The Accept header for the services that answer in JSON or XML.
+    pub(crate) fn accept(&self) -> HeaderValue {
+        self.settings.response_format.to_string().parse().unwrap()
+    }
+
+    /// Checks the quota guard and claims a throttling slot, returning how long to wait before
+    /// sending `request`.
+    pub(crate) fn before_send(&self, request: &OpsRequest) -> Result<Duration, OpsError> {
+        let Some(service) = request.service else {
+            return Ok(Duration::ZERO);
+        };
+        if let Some(quota_guard) = &self.settings.quota_guard {
+            self.quota.check(quota_guard)?;
+        }
+        if !self.settings.pace_requests {
+            return Ok(Duration::ZERO);
+        }
+        let wait = self.throttler.reserve(service);
+        if !wait.is_zero() {
+            debug!(target: "throttling", "Waiting {:?} before the next {} request", wait, service);
+        }
+        Ok(wait)
+    }
+
+    /// Records what the response says about throttling and quota, and turns failures into errors.
+    pub(crate) fn handle_response(
+        &self,
+        request: &OpsRequest,
+        response: OpsResponse,
+    ) -> Result<OpsResponse, OpsError> {
+        debug!(
+            target: "ops_client",
+            "{} {} -> {} ({} bytes in {:?})",
+            request.method,
+            response.url,
+            response.status,
+            response.size(),
+            response.elapsed
+        );
+        self.quota.record(&response.headers);
+        match &response.throttling {
+            Some(control) => self.throttler.update(control),
+            None if response.headers.contains_key("X-Throttling-Control") => {
+                debug!(target: "throttling", "Ignoring odd X-Throttling-Control {:?}", response.headers.get("X-Throttling-Control"))
+            }
+            None => {}
+        }
+        if response.status.is_success() {
+            return Ok(response);
+        }
 
-        let auth_url = "https://ops.epo.org/3.2/auth/accesstoken";
+        let body = String::from_utf8_lossy(&response.body).into_owned();
+        let ops_error = OpsError::from_response(response.status, &response.headers, body);
+        error!(target: "ops_client", "Request failed: {}", ops_error);
+        if let (Some(service), OpsError::Throttled { retry_after, .. }) =
+            (request.service, &ops_error)
+        {
+            self.throttler.back_off(service, *retry_after);
+        }
+        Err(ops_error)
+    }
+
+    /// How long to wait before trying `request` again after it failed with `error` on `attempt`,
+    /// or `None` if it shouldn't be retried.
+    pub(crate) fn retry_delay(
+        &self,
+        request: &OpsRequest,
+        attempt: u32,
+        error: &OpsError,
+    ) -> Option<Duration> {
+        let retry_policy = &self.settings.retry_policy;
+        if attempt >= retry_policy.max_attempts || !retry_policy.is_retryable(error) {
+            return None;
+        }
+        let delay = retry_policy.delay(attempt, error);
+        debug!(
+            target: "ops_client",
+            "Attempt {} of {} failed ({}), retrying in {:?}",
+            attempt, request.url, error, delay
+        );
+        Some(delay)
+    }
+
+    fn published_data_url(&self) -> String {
+        self.settings.endpoints.service_url("published-data")
+    }
+
+    fn register_url(&self) -> String {
+        self.settings.endpoints.service_url("register")
+    }
+
+    pub(crate) fn token_request(&self) -> OpsRequest {
+        let formatted_credentials = self.credentials.format_credentials();
+
+        let auth_url = self.settings.endpoints.auth_url();
         let data = Some("grant_type=client_credentials".to_string());
         let mut headers = HeaderMap::new();
         headers.insert(
@@ -125,104 +448,728 @@ pub fn get_auth_token(epo_credentials: EpoOpsCredentials) -> String {
             "Content-Type",
             "application/x-www-form-urlencoded".parse().unwrap(),
         );
+        OpsRequest {
+            method: Method::POST,
+            url: auth_url,
+            headers,
+            body: data,
+            needs_auth: NeedsAuth::No,
+            service: None,
+        }
+    }
 
-        let response_body = do_post_request(auth_url, data, headers, NeedsAuth::No);
-
-        let response_body_json: Result<TokenResponse, serde_json::Error> =
-            serde_json::from_str(&response_body);
-        let json_token = match response_body_json {
-            Ok(json_token) => json_token,
-            Err(e) => {
-                dbg!(e);
-                dbg!(response_body);
-                panic!("Error decoding the JSON - the data layout might've changed?");
+    pub(crate) fn store_token(
+        &self,
+        response: Result<OpsResponse, OpsError>,
+        requested_at: Instant,
+    ) -> Result<String, OpsError> {
+        let response = match response {
+            Ok(response) => response,
+            // Whatever the reason, the caller can't get a token from these credentials
+            Err(OpsError::OpsFault {
+                status, message, ..
+            }) => return Err(OpsError::Auth { status, message }),
+            Err(OpsError::HttpStatus { status, body }) => {
+                return Err(OpsError::Auth {
+                    status,
+                    message: body,
+                });
             }
+            Err(e) => return Err(e),
         };
-        token_mutex.access_token = json_token.access_token.to_string();
-        token_mutex.expires_in = json_token.expires_in.parse().unwrap();
-        token_mutex.issued_at = json_token.issued_at.parse().unwrap();
-        token_mutex.access_token.clone()
-    } else {
-        debug!(target: "authentication", "Used previous authentication token");
-        token_mutex.access_token.clone()
+
+        let json_token: TokenResponse = response.json()?;
+        self.token.store(&json_token, requested_at)
+    }
+
+    pub(crate) fn publication_bulk_request(
+        &self,
+        patents_details: &[PatentDetails],
+        constituents: &[PublicationConstituents],
+    ) -> Result<OpsRequest, OpsError> {
+        // All of the PatentDetails need to be the same - convert_number can turn one into the other
+        //  using the number-service (3.3 of the EPO OPS doc) beforehand
+        let first_patent = check_bulk_numbers(patents_details)?;
+
+        let mut url = any_reference_type_path(first_patent, &self.published_data_url())?;
+        push_constituents(&mut url, constituents, PublicationConstituents::Biblio);
+        let data = bulk_body(patents_details);
+        let mut headers = HeaderMap::new();
+        headers.insert("Content-Type", "text/plain".parse().unwrap());
+        headers.insert("Accept", self.accept());
+        Ok(OpsRequest::post(
+            &url,
+            Some(data),
+            headers,
+            ServiceKind::Retrieval,
+        ))
+    }
+
+    pub(crate) fn publication_request(
+        &self,
+        patent_details: &PatentDetails,
+        constituents: &[PublicationConstituents],
+    ) -> Result<OpsRequest, OpsError> {
+        let mut url = any_reference_type_path(patent_details, &self.published_data_url())?;
+        push_constituents(&mut url, constituents, PublicationConstituents::Biblio);
+        let data = Some(patent_details.to_string());
+        let mut headers = HeaderMap::new();
+        headers.insert("Content-Type", "text/plain".parse().unwrap());
+        headers.insert("Accept", self.accept());
+        Ok(OpsRequest::post(
+            &url,
+            data,
+            headers,
+            ServiceKind::Retrieval,
+        ))
+    }
+
+    /// The request for one page (e.g. "1-100") of a register search.
+    pub(crate) fn register_search_request(&self, query_string: &str, range: &str) -> OpsRequest {
+        let url = format!("{}/search", self.register_url());
+        let mut headers = HeaderMap::new();
+        headers.insert("Content-Type", "text/plain".parse().unwrap());
+        headers.insert("Accept", self.accept());
+        headers.insert("Range", range.parse().unwrap());
+        let mut data = "q=".to_string();
+        data.push_str(&encode_input(query_string));
+        debug!(target: "register", "Running search with query {}, range {}", data, range);
+        OpsRequest::post(&url, Some(data), headers, ServiceKind::Search)
+    }
+
+    pub(crate) fn register_request(
+        &self,
+        patent_details: &PatentDetails,
+        constituents: &[RegisterConstituents],
+    ) -> Result<OpsRequest, OpsError> {
+        let mut url = reference_type_path(patent_details, &self.register_url())?;
+        push_constituents(&mut url, constituents, RegisterConstituents::Biblio);
+        let data = Some(patent_details.to_string());
+        let mut headers = HeaderMap::new();
+        headers.insert("Content-Type", "text/plain".parse().unwrap());
+        headers.insert("Accept", self.accept());
+        Ok(OpsRequest::post(
+            &url,
+            data,
+            headers,
+            ServiceKind::Retrieval,
+        ))
+    }
+
+    pub(crate) fn usage_request(&self, from: NaiveDate, to: NaiveDate) -> OpsRequest {
+        let url = format!(
+            "{}?timeRange={}~{}",
+            self.settings.endpoints.usage_url(),
+            from.format("%d/%m/%Y"),
+            to.format("%d/%m/%Y")
+        );
+        let mut headers = HeaderMap::new();
+        headers.insert("Accept", "application/json".parse().unwrap());
+        OpsRequest::get(&url, headers, ServiceKind::Other)
     }
+
+    pub(crate) fn get_publication_bulk<'a>(
+        &'a self,
+        patents_details: &[PatentDetails],
+        constituents: &'a [PublicationConstituents],
+    ) -> Bulk<'a, Vec<Value>> {
+        Bulk::new(
+            patents_details,
+            |patent_details| reference_type_name(patent_details).map(drop),
+            move |chunk| self.publication_bulk_request(chunk, constituents),
+            BulkResults::add_publication_response,
+        )
+    }
+
+    /// Every page of a register search, one response per page.
+    pub(crate) fn search_register<'a>(&'a self, query_string: &'a str) -> Pages<'a> {
+        Pages::new(
+            "register",
+            move |range| Ok(self.register_search_request(query_string, range)),
+            parse_register_search_total,
+        )
+    }
+
+    pub(crate) fn get_usage_data_response(&self, from: NaiveDate, to: NaiveDate) -> Single {
+        Single::new(self.usage_request(from, to))
+    }
+
+    pub(crate) fn get_usage_data(
+        &self,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> impl Exchange<Output = Usage> {
+        self.get_usage_data_response(from, to)
+            .map(|response| parse_usage(&response))
+    }
+}
+
+/// Reads the total number of results out of the first page of a register search.
+pub(crate) fn parse_register_search_total(result: &OpsResponse) -> Result<u32, OpsError> {
+    let result_json: Value = result.json()?;
+    let num_results: u32 = result_json
+        .pointer("/ops:world-patent-data/ops:register-search/@total-result-count")
+        .and_then(|count| count.as_str())
+        .ok_or_else(|| {
+            error!(target: "register", "Couldn't find @total-result-count - malformed JSON");
+            OpsError::Decode("Couldn't find @total-result-count in register search".into())
+        })?
+        .parse()
+        .map_err(|e| OpsError::Decode(Box::new(e)))?;
+    debug!(target: "register", "Found {} results", num_results);
+    Ok(num_results)
 }
 
-pub fn get_publication_bulk(
-    patents_details: &Vec<PatentDetails>,
-    constituents: &Vec<PublicationConstituents>,
-) -> String {
-    assert!(patents_details.len() > 1);
-    // All of the PatentDetails need to be the same - I could figure out how to convert between the 2
-    //  optionally using the number-service API in 3.3 of the EPO OPS doc
-    let first_patent = patents_details.first().unwrap();
-    #[cfg(debug_assertions)]
-    {
-        for patent_details in patents_details {
-            assert_eq!(patent_details.reference_type, first_patent.reference_type);
+/// The ranges of the pages after the first one needed to get all `num_results` results.
+pub(crate) fn remaining_search_ranges(num_results: u32) -> Vec<String> {
+    let results_left = num_results.saturating_sub(100);
+    let remainder = results_left % 100;
+    let num_loops = results_left / 100 + { if remainder > 0 { 1 } else { 0 } };
+    (0..num_loops)
+        .map(|loop_number| {
+            format!(
+                "{}-{}",
+                (loop_number + 1) * 100 + 1,
+                (loop_number + 2) * 100
+            )
+        })
+        .collect()
+}
+
+pub(crate) fn parse_usage(usage: &OpsResponse) -> Result<Usage, OpsError> {
+    usage.json()
+}
+
+/// A `ClientCore` with made up credentials and the default settings, for checking requests.
+#[cfg(test)]
+pub(crate) fn test_core() -> ClientCore {
+    ClientCore::new(
+        EpoOpsCredentials::new("key", "secret"),
+        OpsClientSettings::default(),
+    )
+    .unwrap()
+}
+
+/// A connection to EPO OPS for one set of credentials.
+///
+/// Each client keeps its own access token, so several clients (i.e. several accounts) can be used
+/// side by side in the same process.
+#[cfg(feature = "blocking")]
+pub struct OpsClient {
+    core: ClientCore,
+    http_client: reqwest::blocking::Client,
+    // Held while fetching a new token, so concurrent callers wait for that one instead of all
+    //  asking for their own
+    token_refresh: Arc<Mutex<()>>,
+}
+
+#[cfg(feature = "blocking")]
+impl OpsClient {
+    pub fn new(
+        credentials: EpoOpsCredentials,
+        settings: OpsClientSettings,
+    ) -> Result<OpsClient, OpsError> {
+        let http_settings = &settings.http;
+        let mut builder = reqwest::blocking::ClientBuilder::new()
+            .redirect(reqwest::redirect::Policy::none())
+            .user_agent(&http_settings.user_agent)
+            .gzip(http_settings.gzip)
+            .connect_timeout(http_settings.connect_timeout)
+            .timeout(http_settings.read_timeout);
+        if let Some(proxy) = http_settings.reqwest_proxy()? {
+            builder = builder.proxy(proxy);
         }
+        let http_client = builder.build()?;
+        Ok(OpsClient {
+            core: ClientCore::new(credentials, settings)?,
+            http_client,
+            token_refresh: Arc::default(),
+        })
     }
 
-    let mut url = match first_patent.reference_type {
-        PatentReferenceType::Publication => {
-            "http://ops.epo.org/rest-services/published-data/publication/epodoc/".to_string()
+    /// A client that asks for `response_format` instead, sharing this one's connections, access
+    /// token, throttling and quota state - e.g. `client.with_response_format(ResponseFormat::Xml)`
+    /// for the odd request in XML.
+    pub fn with_response_format(&self, response_format: ResponseFormat) -> OpsClient {
+        OpsClient {
+            core: self.core.with_response_format(response_format),
+            http_client: self.http_client.clone(),
+            token_refresh: Arc::clone(&self.token_refresh),
         }
-        PatentReferenceType::Application => {
-            "http://ops.epo.org/rest-services/published-data/application/epodoc/".to_string()
+    }
+
+    pub fn settings(&self) -> &OpsClientSettings {
+        &self.core.settings
+    }
+
+    /// The fair use quota consumption reported with the most recent responses.
+    pub fn quota_status(&self) -> QuotaStatus {
+        self.core.quota_status()
+    }
+
+    fn execute(&self, request: OpsRequest) -> Result<OpsResponse, OpsError> {
+        let mut attempt = 1;
+        loop {
+            match self.execute_once(&request) {
+                Err(e) => match self.core.retry_delay(&request, attempt, &e) {
+                    Some(delay) => {
+                        thread::sleep(delay);
+                        attempt += 1;
+                    }
+                    None => return Err(e),
+                },
+                result => return result,
+            }
         }
-        _ => unimplemented!("Only supporting publication and application numbers"),
-    };
-    if constituents.is_empty() {
-        url.push_str(&PublicationConstituents::Biblio.to_string());
-    } else {
-        for constituent in constituents {
-            url.push_str(&constituent.to_string());
-            url.push(',');
+    }
+
+    fn run<E: Exchange>(&self, mut exchange: E) -> Result<E::Output, OpsError> {
+        while let Some(request) = exchange.next_request()? {
+            exchange.handle(self.execute(request))?;
         }
-        url.pop();
+        exchange.finish()
     }
-    let mut data = String::new();
-    for patent_details in patents_details {
-        data.push_str(&patent_details.to_string());
-        data.push('\n');
+
+    fn execute_once(&self, request: &OpsRequest) -> Result<OpsResponse, OpsError> {
+        match request.needs_auth {
+            NeedsAuth::No => self.send(request, None),
+            NeedsAuth::Yes => {
+                let auth_token = self.get_auth_token()?;
+                match self.send(request, Some(&auth_token)) {
+                    // The token can be revoked or expire early on OPS's side - get a new one
+                    //  and give it one more go
+                    Err(OpsError::Auth { .. }) => {
+                        debug!(target: "authentication", "Access token rejected, re-authenticating");
+                        self.core.token.invalidate(&auth_token);
+                        let auth_token = self.get_auth_token()?;
+                        self.send(request, Some(&auth_token))
+                    }
+                    result => result,
+                }
+            }
+        }
     }
-    let mut headers = HeaderMap::new();
-    headers.insert("Content-Type", "text/plain".parse().unwrap());
-    headers.insert("Accept", "application/json".parse().unwrap());
-    do_post_request(&url, Some(data), headers, NeedsAuth::Yes)
-}
 
-pub fn get_publication(
-    patent_details: &PatentDetails,
-    constituents: &Vec<PublicationConstituents>,
-) -> String {
-    // This function is 90% the same as the get_register... refactor?
-    let mut url = match patent_details.reference_type {
-        PatentReferenceType::Publication => {
-            "http://ops.epo.org/rest-services/published-data/publication/epodoc/".to_string()
-        }
-        PatentReferenceType::Application => {
-            "http://ops.epo.org/rest-services/published-data/application/epodoc/".to_string()
-        }
-        _ => unimplemented!("Only supporting publication and application numbers"),
-    };
-    if constituents.is_empty() {
-        url.push_str(&PublicationConstituents::Biblio.to_string());
-    } else {
-        for constituent in constituents {
-            url.push_str(&constituent.to_string());
-            url.push(',');
+    fn send(
+        &self,
+        request: &OpsRequest,
+        auth_token: Option<&str>,
+    ) -> Result<OpsResponse, OpsError> {
+        let mut builder = self
+            .http_client
+            .request(request.method.clone(), &request.url)
+            .headers(request.headers.clone());
+        if let Some(auth_token) = auth_token {
+            builder = builder.bearer_auth(auth_token);
         }
-        url.pop();
+        if let Some(body) = &request.body {
+            builder = builder.body(body.clone());
+        }
+
+        let wait = self.core.before_send(request)?;
+        if !wait.is_zero() {
+            thread::sleep(wait);
+        }
+        let sent_at = Instant::now();
+        let resp = builder.send()?;
+        let status = resp.status();
+        let headers = resp.headers().clone();
+        let body = resp.bytes()?.to_vec();
+        let response = OpsResponse::new(status, headers, body, &request.url, sent_at.elapsed());
+        self.core.handle_response(request, response)
+    }
+
+    pub fn get_auth_token(&self) -> Result<String, OpsError> {
+        if let Some(auth_token) = self.core.token.valid_token() {
+            debug!(target: "authentication", "Used previous authentication token");
+            return Ok(auth_token);
+        }
+        let _refreshing = self.token_refresh.lock().unwrap();
+        // Someone else may have refreshed the token while we were waiting for the lock
+        if let Some(auth_token) = self.core.token.valid_token() {
+            debug!(target: "authentication", "Used token refreshed by another caller");
+            return Ok(auth_token);
+        }
+
+        debug!(target: "authentication", "Need new authentication token");
+        let requested_at = Instant::now();
+        let response = self.execute(self.core.token_request());
+        self.core.store_token(response, requested_at)
+    }
+
+    /// Published data for any number of publications, applications or priorities, in bulk
+    /// requests of up to `MAX_BULK_NUMBERS` numbers of the same type. Each number gets all of its
+    /// `exchange-document`s (e.g. the A1 and B1); numbers OPS doesn't have or can't take end up in
+    /// the failures instead of failing the others.
+    pub fn get_publication_bulk(
+        &self,
+        patents_details: &[PatentDetails],
+        constituents: &[PublicationConstituents],
+    ) -> Result<BulkResults<Vec<Value>>, OpsError> {
+        self.run(
+            self.core
+                .get_publication_bulk(patents_details, constituents),
+        )
+    }
+
+    pub fn get_publication(
+        &self,
+        patent_details: &PatentDetails,
+        constituents: &[PublicationConstituents],
+    ) -> Result<OpsResponse, OpsError> {
+        self.execute(
+            self.core
+                .publication_request(patent_details, constituents)?,
+        )
+    }
+
+    /// The abstract of a publication in every language OPS has it in.
+    pub fn get_abstracts(&self, patent_details: &PatentDetails) -> Result<Vec<Abstract>, OpsError> {
+        self.run(self.core.get_abstracts(patent_details)?)
+    }
+
+    pub fn get_abstracts_response(
+        &self,
+        patent_details: &PatentDetails,
+    ) -> Result<OpsResponse, OpsError> {
+        self.run(self.core.get_abstracts_response(patent_details)?)
+    }
+
+    /// The abstract of a publication in the first of `languages` (e.g. `["en", "de", "fr"]`) that
+    /// OPS has it in, or else in whichever language comes first. The response it's read from is
+    /// the one `get_abstracts_response` gives back.
+    pub fn get_abstract(
+        &self,
+        patent_details: &PatentDetails,
+        languages: &[&str],
+    ) -> Result<Option<Abstract>, OpsError> {
+        self.run(self.core.get_abstract(patent_details, languages)?)
+    }
+
+    /// Every publication stage (A1, B1, ...) of a publication or application, with the biblio of
+    /// each.
+    pub fn get_full_cycle(&self, patent_details: &PatentDetails) -> Result<FullCycle, OpsError> {
+        self.run(self.core.get_full_cycle(patent_details)?)
+    }
+
+    pub fn get_full_cycle_response(
+        &self,
+        patent_details: &PatentDetails,
+    ) -> Result<OpsResponse, OpsError> {
+        self.run(self.core.get_full_cycle_response(patent_details)?)
+    }
+
+    /// Runs a register search, getting every page of results (one response per page).
+    pub fn search_register(&self, query_string: &str) -> Result<Vec<OpsResponse>, OpsError> {
+        self.run(self.core.search_register(query_string))
+    }
+
+    /// Runs a CQL search of the published data, getting every page of results - up to
+    /// `MAX_PUBLISHED_SEARCH_RESULTS`, which is as far as OPS goes.
+    pub fn search_published(
+        &self,
+        query_string: &str,
+        constituents: &[PublicationConstituents],
+    ) -> Result<PublishedSearchResults, OpsError> {
+        self.run(self.core.search_published(query_string, constituents))
+    }
+
+    pub fn search_published_response(
+        &self,
+        query_string: &str,
+        constituents: &[PublicationConstituents],
+    ) -> Result<Vec<OpsResponse>, OpsError> {
+        self.run(
+            self.core
+                .search_published_response(query_string, constituents),
+        )
+    }
+
+    pub fn get_register_info(
+        &self,
+        patent_details: &PatentDetails,
+        constituents: &[RegisterConstituents],
+    ) -> Result<OpsResponse, OpsError> {
+        self.execute(self.core.register_request(patent_details, constituents)?)
+    }
+
+    /// Register info for any number of applications or publications, in bulk requests of up to
+    /// `MAX_BULK_NUMBERS` numbers. A number OPS can't find, or that the register doesn't take
+    /// (e.g. a priority number), ends up in the failures instead of failing the others; errors
+    /// that aren't down to the numbers (e.g. the quota running out) still stop the whole lot.
+    pub fn get_register_info_bulk(
+        &self,
+        patents_details: &[PatentDetails],
+        constituents: &[RegisterConstituents],
+    ) -> Result<BulkResults<Value>, OpsError> {
+        self.run(
+            self.core
+                .get_register_info_bulk(patents_details, constituents),
+        )
+    }
+
+    /// The INPADOC family of a publication, application or priority number.
+    pub fn get_family(
+        &self,
+        patent_details: &PatentDetails,
+        constituents: &[FamilyConstituents],
+    ) -> Result<Family, OpsError> {
+        self.run(self.core.get_family(patent_details, constituents)?)
+    }
+
+    pub fn get_family_response(
+        &self,
+        patent_details: &PatentDetails,
+        constituents: &[FamilyConstituents],
+    ) -> Result<OpsResponse, OpsError> {
+        self.run(
+            self.core
+                .get_family_response(patent_details, constituents)?,
+        )
+    }
+
+    /// The simple family of a publication, application or priority number - the biblio and
+    /// abstract constituents come with each `Equivalent`, the images one is only in the
+    /// response from `get_equivalents_response`.
+    pub fn get_equivalents(
+        &self,
+        patent_details: &PatentDetails,
+        constituents: &[EquivalentsConstituents],
+    ) -> Result<Vec<Equivalent>, OpsError> {
+        self.run(self.core.get_equivalents(patent_details, constituents)?)
+    }
+
+    pub fn get_equivalents_response(
+        &self,
+        patent_details: &PatentDetails,
+        constituents: &[EquivalentsConstituents],
+    ) -> Result<OpsResponse, OpsError> {
+        self.run(
+            self.core
+                .get_equivalents_response(patent_details, constituents)?,
+        )
+    }
+
+    /// Which full text parts (description, claims) and languages OPS has for a publication.
+    pub fn get_fulltext_inquiry(
+        &self,
+        patent_details: &PatentDetails,
+    ) -> Result<FulltextInquiry, OpsError> {
+        self.run(self.core.get_fulltext_inquiry(patent_details)?)
+    }
+
+    pub fn get_fulltext_inquiry_response(
+        &self,
+        patent_details: &PatentDetails,
+    ) -> Result<OpsResponse, OpsError> {
+        self.run(self.core.get_fulltext_inquiry_response(patent_details)?)
+    }
+
+    /// The description of a publication, one per language OPS returned.
+    pub fn get_description(
+        &self,
+        patent_details: &PatentDetails,
+    ) -> Result<Vec<Description>, OpsError> {
+        self.run(self.core.get_description(patent_details)?)
+    }
+
+    pub fn get_description_response(
+        &self,
+        patent_details: &PatentDetails,
+    ) -> Result<OpsResponse, OpsError> {
+        self.run(self.core.get_description_response(patent_details)?)
+    }
+
+    /// The claims of a publication, one set per language OPS returned.
+    pub fn get_claims(&self, patent_details: &PatentDetails) -> Result<Vec<Claims>, OpsError> {
+        self.run(self.core.get_claims(patent_details)?)
+    }
+
+    pub fn get_claims_response(
+        &self,
+        patent_details: &PatentDetails,
+    ) -> Result<OpsResponse, OpsError> {
+        self.run(self.core.get_claims_response(patent_details)?)
+    }
+
+    /// Which images (full document, drawings, first page clipping) OPS has for a publication.
+    pub fn get_image_inquiry(
+        &self,
+        patent_details: &PatentDetails,
+    ) -> Result<ImageInquiry, OpsError> {
+        self.run(self.core.get_image_inquiry(patent_details)?)
+    }
+
+    pub fn get_image_inquiry_response(
+        &self,
+        patent_details: &PatentDetails,
+    ) -> Result<OpsResponse, OpsError> {
+        self.run(self.core.get_image_inquiry_response(patent_details)?)
+    }
+
+    /// One page (starting at 1) of the images behind `link`, as found in a `DocumentInstance`.
+    pub fn get_image_page(
+        &self,
+        link: &str,
+        page: u32,
+        format: ImageFormat,
+    ) -> Result<OpsResponse, OpsError> {
+        self.execute(self.core.image_page_request(link, page, format)?)
+    }
+
+    /// Downloads every page of the full document into `folder` and joins them into one PDF,
+    /// returning where it was written. Pages already in `folder` aren't downloaded again.
+    pub fn download_full_document(
+        &self,
+        patent_details: &PatentDetails,
+        folder: &Path,
+    ) -> Result<PathBuf, OpsError> {
+        let inquiry = self.get_image_inquiry(patent_details)?;
+        let full_document = inquiry
+            .full_document()
+            .ok_or_else(|| no_full_document(patent_details))?;
+
+        let mut pages = Vec::new();
+        for page in 1..=full_document.number_of_pages {
+            let page_path = full_document_page_path(folder, patent_details, page);
+            if page_path.exists() {
+                pages.push(fs::read(&page_path)?);
+                continue;
+            }
+            debug!(target: "images", "Getting page {} of {}", page, patent_details);
+            let response = self.get_image_page(&full_document.link, page, ImageFormat::Pdf)?;
+            if let Some(page_folder) = page_path.parent() {
+                fs::create_dir_all(page_folder)?;
+            }
+            fs::write(&page_path, &response.body)?;
+            pages.push(response.body);
+        }
+
+        let document_path = full_document_path(folder, patent_details);
+        fs::write(&document_path, merge_pdfs(&pages)?)?;
+        Ok(document_path)
+    }
+
+    /// Converts a number between formats with the number-service, e.g. to get the docdb
+    /// version of a number given in its original format.
+    pub fn convert_number(
+        &self,
+        patent_details: &PatentDetails,
+        input_format: NumberFormat,
+        output_format: NumberFormat,
+    ) -> Result<PatentDetails, OpsError> {
+        self.run(
+            self.core
+                .convert_number(patent_details, input_format, output_format)?,
+        )
+    }
+
+    pub fn convert_number_response(
+        &self,
+        patent_details: &PatentDetails,
+        input_format: NumberFormat,
+        output_format: NumberFormat,
+    ) -> Result<OpsResponse, OpsError> {
+        self.run(
+            self.core
+                .convert_number_response(patent_details, input_format, output_format)?,
+        )
+    }
+
+    /// The INPADOC legal events of a publication.
+    pub fn get_legal_events(
+        &self,
+        patent_details: &PatentDetails,
+    ) -> Result<Vec<LegalEvent>, OpsError> {
+        self.run(self.core.get_legal_events(patent_details)?)
+    }
+
+    pub fn get_legal_events_response(
+        &self,
+        patent_details: &PatentDetails,
+    ) -> Result<OpsResponse, OpsError> {
+        self.run(self.core.get_legal_events_response(patent_details)?)
+    }
+
+    /// A CPC symbol (e.g. "A01B 1/00") with its title and the scheme below it down to `depth`,
+    /// plus the symbols above it when `ancestors` is set.
+    pub fn get_cpc(
+        &self,
+        symbol: &str,
+        depth: CpcDepth,
+        ancestors: bool,
+    ) -> Result<CpcClassification, OpsError> {
+        self.run(self.core.get_cpc(symbol, depth, ancestors))
+    }
+
+    pub fn get_cpc_response(
+        &self,
+        symbol: &str,
+        depth: CpcDepth,
+        ancestors: bool,
+    ) -> Result<OpsResponse, OpsError> {
+        self.run(self.core.get_cpc_response(symbol, depth, ancestors))
+    }
+
+    /// Keyword search of the CPC scheme, most relevant symbols first.
+    pub fn search_cpc(&self, keywords: &str) -> Result<Vec<CpcSearchResult>, OpsError> {
+        self.run(self.core.search_cpc(keywords))
+    }
+
+    pub fn search_cpc_response(&self, keywords: &str) -> Result<OpsResponse, OpsError> {
+        self.run(self.core.search_cpc_response(keywords))
+    }
+
+    /// An illustration from the CPC scheme by its file name, e.g. "1000.gif".
+    pub fn get_cpc_media(&self, media_name: &str) -> Result<OpsResponse, OpsError> {
+        self.execute(self.core.cpc_media_request(media_name))
+    }
+
+    /// Maps a symbol from one classification scheme to another, e.g. ECLA to CPC.
+    pub fn map_classification(
+        &self,
+        symbol: &str,
+        from: ClassificationScheme,
+        to: ClassificationScheme,
+    ) -> Result<Vec<ClassificationMapping>, OpsError> {
+        self.run(self.core.map_classification(symbol, from, to))
+    }
+
+    pub fn map_classification_response(
+        &self,
+        symbol: &str,
+        from: ClassificationScheme,
+        to: ClassificationScheme,
+    ) -> Result<OpsResponse, OpsError> {
+        self.run(self.core.map_classification_response(symbol, from, to))
+    }
+
+    pub fn get_usage_data(&self, from: NaiveDate, to: NaiveDate) -> Result<Usage, OpsError> {
+        self.run(self.core.get_usage_data(from, to))
+    }
+
+    pub fn get_usage_data_response(
+        &self,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<OpsResponse, OpsError> {
+        self.run(self.core.get_usage_data_response(from, to))
+    }
+
+    /// The usage statistics for `from` to `to` as numbers, per day and per service, with what's
+    /// needed to compare them against the weekly fair use quota. The response it's read from is
+    /// the one `get_usage_data_response` gives back.
+    pub fn get_usage_report(
+        &self,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Result<UsageReport, OpsError> {
+        self.run(self.core.get_usage_report(from, to))
     }
-    let data = Some(patent_details.to_string());
-    let mut headers = HeaderMap::new();
-    headers.insert("Content-Type", "text/plain".parse().unwrap());
-    headers.insert("Accept", "application/json".parse().unwrap());
-    do_post_request(&url, data, headers, NeedsAuth::Yes)
 }
+
 // See input construction rule 3 of EPO OPS 3.2 doc
-fn encode_input(unquoted_string: &str) -> String {
+pub(crate) fn encode_input(unquoted_string: &str) -> String {
     let mut quoted_string = String::new();
     // I'm sure there's a more efficient way to do this - but I think it'll work.
     //  The strings will only be like 20 chars max, so it'll probably be fine.
@@ -257,128 +1204,6 @@ fn encode_input(unquoted_string: &str) -> String {
     quoted_string
 }
 
-pub fn search_register(query_string: &str) -> Vec<String> {
-    let mut results: Vec<String> = Vec::new();
-    let url = "http://ops.epo.org/rest-services/register/search";
-    let mut headers = HeaderMap::new();
-    headers.insert("Range", "1-100".parse().unwrap());
-    headers.insert("Content-Type", "text/plain".parse().unwrap());
-    headers.insert("Accept", "application/json".parse().unwrap());
-    let mut data = "q=".to_string();
-    data.push_str(&encode_input(query_string));
-    let data = data;
-    debug!(target: "register", "Running search with query: {}", data);
-    // Should probably correct the lifetimes stuff with the query_string
-    let result = do_post_request(url, Some(data.clone()), headers, NeedsAuth::Yes);
-    let result_json: Result<Value, serde_json::Error> = serde_json::from_str(&result);
-    let num_results: u32;
-    match result_json {
-        Err(e) => {
-            error!(target: "register", "Couldn't decode JSON from EPO OPS");
-            dbg!(e);
-            panic!("Couldn't decode JSON from EPO OPS");
-        }
-        Ok(json) => match json.get("ops:world-patent-data") {
-            Some(ops_world_patent_data) => match ops_world_patent_data.get("ops:register-search") {
-                Some(ops_register_search) => {
-                    let result = ops_register_search
-                        .get("@total-result-count")
-                        .unwrap()
-                        .as_str()
-                        .unwrap();
-                    num_results = result.parse().unwrap();
-                }
-                None => {
-                    error!(target: "register", "Couldn't find ops:register-search - malformed JSON");
-                    panic!("Couldn't decode JSON from EPO OPS");
-                }
-            },
-            None => {
-                error!(
-                    target: "register",
-                    "Couldn't find ops:world-patent-data - malformed JSON"
-                );
-                panic!("Couldn't decode JSON from EPO OPS");
-            }
-        },
-    }
-    results.push(result);
-    debug!(target: "register", "Found {} results", num_results);
-    let results_left = num_results - 100;
-    let remainder = num_results % 100;
-    let num_loops = results_left / 100 + { if remainder > 0 { 1 } else { 0 } };
-    for loop_number in 0..num_loops {
-        let range_str = format!(
-            "{}-{}",
-            (loop_number + 1) * 100 + 1,
-            (loop_number + 2) * 100
-        );
-        debug!(target: "register", "Getting items {} of the current search", range_str);
-        let mut headers = HeaderMap::new();
-        headers.insert("Content-Type", "text/plain".parse().unwrap());
-        headers.insert("Accept", "application/json".parse().unwrap());
-        headers.insert("Range", range_str.parse().unwrap());
-        results.push(do_post_request(
-            url,
-            Some(data.clone()),
-            headers,
-            NeedsAuth::Yes,
-        ));
-    }
-
-    results
-}
-
-pub fn get_register_info(
-    patent_details: &PatentDetails,
-    constituents: &Vec<RegisterConstituents>,
-) -> String {
-    let mut url = match patent_details.reference_type {
-        PatentReferenceType::Publication => {
-            "https://ops.epo.org/rest-services/register/publication/epodoc/".to_string()
-        }
-        PatentReferenceType::Application => {
-            "https://ops.epo.org/rest-services/register/application/epodoc/".to_string()
-        }
-        _ => unimplemented!("Only supporting publication and application numbers"),
-    };
-
-    if constituents.is_empty() {
-        url.push_str(&RegisterConstituents::Biblio.to_string());
-    } else {
-        for constituent in constituents {
-            url.push_str(&constituent.to_string());
-            url.push(',');
-        }
-        url.pop();
-    }
-    let data = Some(patent_details.to_string());
-    let mut headers = HeaderMap::new();
-    headers.insert("Content-Type", "text/plain".parse().unwrap());
-    headers.insert("Accept", "application/json".parse().unwrap());
-    do_post_request(&url, data, headers, NeedsAuth::Yes)
-}
-
-pub fn get_usage_data(from: NaiveDate, to: NaiveDate) -> Usage {
-    let url = format!(
-        "https://ops.epo.org/3.2/developers/me/stats/usage?timeRange={}~{}",
-        from.format("%d/%m/%Y"),
-        to.format("%d/%m/%Y")
-    );
-    let mut headers = HeaderMap::new();
-    headers.insert("Accept", "application/json".parse().unwrap());
-    let usage = do_get_request(&url, headers, NeedsAuth::Yes);
-    let response_body_json: Result<Usage, serde_json::Error> = serde_json::from_str(&usage);
-    match response_body_json {
-        Ok(good_usage) => good_usage,
-        Err(e) => {
-            dbg!(e);
-            dbg!(usage);
-            panic!("Error decoding the JSON - the data layout might've changed?");
-        }
-    }
-}
-
 pub enum RegisterConstituents {
     Biblio,
     ProceduralSteps,
@@ -401,6 +1226,9 @@ pub enum PublicationConstituents {
     Biblio,
     Abstract,
     FullCycle,
+    // These two can't be combined with the others in one request
+    Description,
+    Claims,
 }
 
 impl fmt::Display for PublicationConstituents {
@@ -409,11 +1237,13 @@ impl fmt::Display for PublicationConstituents {
             PublicationConstituents::Biblio => write!(f, "biblio"),
             PublicationConstituents::Abstract => write!(f, "abstract"),
             PublicationConstituents::FullCycle => write!(f, "full-cycle"),
+            PublicationConstituents::Description => write!(f, "description"),
+            PublicationConstituents::Claims => write!(f, "claims"),
         }
     }
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug)]
 pub enum PatentReferenceType {
     Publication,
     Application,
@@ -421,7 +1251,7 @@ pub enum PatentReferenceType {
     Unknown,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct PatentDetails {
     pub country_code: String,
     pub number: String,
@@ -430,10 +1260,9 @@ pub struct PatentDetails {
     pub reference_type: PatentReferenceType,
 }
 
-/* There is this "docdb" format which requires '.'s between the different
- * components. I'm not quite sure how/where it's used right now (the EPO
- * docdb website bulk downloadload themselves don't even seem to use
- * it. I'm not going to implement it right now. This doesn't handle
+/* This is the "epodoc" format, which is what all of the requests use. The
+ * "docdb" and "original" formats (with '.'s between the different
+ * components) are in number.rs, for the number-service. This doesn't handle
  * escaping.
  */
 
@@ -468,4 +1297,144 @@ mod tests {
             encode_input("DE20 2007 016 308.8")
         );
     }
+
+    #[test]
+    fn test_search_ranges() {
+        assert!(remaining_search_ranges(0).is_empty());
+        assert!(remaining_search_ranges(100).is_empty());
+        assert_eq!(remaining_search_ranges(101), vec!["101-200"]);
+        assert_eq!(remaining_search_ranges(250), vec!["101-200", "201-300"]);
+    }
+
+    #[test]
+    fn test_endpoints() {
+        let endpoints = Endpoints::default();
+        assert!(endpoints.validate().is_ok());
+        assert_eq!(
+            endpoints.service_url("register"),
+            "https://ops.epo.org/3.2/rest-services/register"
+        );
+        assert_eq!(
+            endpoints.auth_url(),
+            "https://ops.epo.org/3.2/auth/accesstoken"
+        );
+
+        let mut endpoints = Endpoints {
+            base_url: "http://localhost:8080/".to_string(),
+            ..Endpoints::default()
+        };
+        assert!(matches!(
+            endpoints.validate(),
+            Err(OpsError::InvalidConfig(_))
+        ));
+        endpoints.allow_insecure_http = true;
+        assert!(endpoints.validate().is_ok());
+        assert_eq!(
+            endpoints.usage_url(),
+            "http://localhost:8080/3.2/developers/me/stats/usage"
+        );
+
+        endpoints.base_url = "ftp://ops.epo.org".to_string();
+        assert!(endpoints.validate().is_err());
+    }
+
+    #[test]
+    fn test_priority_publication_request() {
+        let core = test_core();
+        let priority = PatentDetails {
+            country_code: "US".to_string(),
+            number: "201113136584".to_string(),
+            kind_code: None,
+            date: None,
+            reference_type: PatentReferenceType::Priority,
+        };
+        let request = core
+            .publication_request(&priority, &[PublicationConstituents::Biblio])
+            .unwrap();
+        assert_eq!(
+            request.url,
+            "https://ops.epo.org/3.2/rest-services/published-data/priority/epodoc/biblio"
+        );
+        assert_eq!(request.body.as_deref(), Some("US201113136584"));
+        assert!(core.register_request(&priority, &[]).is_err());
+
+        let unknown = PatentDetails {
+            reference_type: PatentReferenceType::Unknown,
+            ..priority
+        };
+        assert!(matches!(
+            core.publication_request(&unknown, &[]),
+            Err(OpsError::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn test_proxy_settings() {
+        let mut http_settings = HttpSettings::default();
+        assert!(http_settings.reqwest_proxy().unwrap().is_none());
+        http_settings.proxy = Some(ProxySettings {
+            url: "http://proxy.example.com:3128".to_string(),
+            username: Some("user".to_string()),
+            password: Some("password".to_string()),
+        });
+        assert!(http_settings.reqwest_proxy().unwrap().is_some());
+        http_settings.proxy = Some(ProxySettings {
+            url: "not a url".to_string(),
+            username: None,
+            password: None,
+        });
+        assert!(matches!(
+            http_settings.reqwest_proxy(),
+            Err(OpsError::InvalidConfig(_))
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "blocking")]
+    fn test_independent_clients() {
+        let first = OpsClient::new(
+            EpoOpsCredentials::new("first_key", "first_secret"),
+            OpsClientSettings::default(),
+        )
+        .unwrap();
+        let mut settings = OpsClientSettings::default();
+        settings.endpoints.base_url = "https://localhost:8443".to_string();
+        let second = OpsClient::new(
+            EpoOpsCredentials::new("second_key", "second_secret"),
+            settings,
+        )
+        .unwrap();
+        assert_ne!(
+            first.settings().endpoints.base_url,
+            second.settings().endpoints.base_url
+        );
+
+        // Same token/throttling/quota state, different Accept header
+        let xml = second.with_response_format(ResponseFormat::Xml);
+        assert_eq!(xml.settings().response_format, ResponseFormat::Xml);
+        assert_eq!(second.settings().response_format, ResponseFormat::Json);
+        assert!(Arc::ptr_eq(&xml.core.token, &second.core.token));
+        let patent_details = PatentDetails {
+            country_code: "EP".to_string(),
+            number: "1000000".to_string(),
+            kind_code: None,
+            date: None,
+            reference_type: PatentReferenceType::Publication,
+        };
+        let request = xml.core.publication_request(&patent_details, &[]).unwrap();
+        assert_eq!(request.headers["Accept"], "application/xml");
+
+        // Priority numbers aren't supported by the register, so this fails before any request
+        let priority = PatentDetails {
+            country_code: "US".to_string(),
+            number: "201113136584".to_string(),
+            kind_code: None,
+            date: None,
+            reference_type: PatentReferenceType::Priority,
+        };
+        assert!(matches!(
+            second.get_register_info(&priority, &[]),
+            Err(OpsError::InvalidInput(_))
+        ));
+    }
 }
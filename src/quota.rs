@@ -0,0 +1,140 @@
+use crate::OpsError;
+use chrono::{DateTime, Utc};
+use reqwest::header::HeaderMap;
+use std::sync::Mutex;
+
+/// The weekly fair use allowance of a free ("non-paying") OPS account, in bytes.
+pub const DEFAULT_WEEKLY_QUOTA: u64 = 4 * 1024 * 1024 * 1024;
+
+/// How much of the fair use quotas OPS last told us had been used.
+///
+/// Both values are in bytes and are `None` until a response carrying them has been seen.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct QuotaStatus {
+    /// From `X-IndividualQuotaPerHour-Used`.
+    pub hourly_used: Option<u64>,
+    /// From `X-RegisteredQuotaPerWeek-Used`.
+    pub weekly_used: Option<u64>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl QuotaStatus {
+    pub(crate) fn from_headers(headers: &HeaderMap) -> QuotaStatus {
+        let header_bytes = |name| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.trim().parse::<u64>().ok())
+        };
+        let hourly_used = header_bytes("X-IndividualQuotaPerHour-Used");
+        let weekly_used = header_bytes("X-RegisteredQuotaPerWeek-Used");
+        let updated_at = if hourly_used.is_some() || weekly_used.is_some() {
+            Some(Utc::now())
+        } else {
+            None
+        };
+        QuotaStatus {
+            hourly_used,
+            weekly_used,
+            updated_at,
+        }
+    }
+
+    /// The share (0.0 - 1.0) of `weekly_quota` that has been used, if known.
+    pub fn weekly_share(&self, weekly_quota: u64) -> Option<f64> {
+        self.weekly_used
+            .map(|used| used as f64 / weekly_quota as f64)
+    }
+}
+
+/// Stops the client from starting new requests once too much of the weekly quota is gone.
+#[derive(Clone, Debug, PartialEq)]
+pub struct QuotaGuard {
+    /// The weekly allowance of the account, in bytes.
+    pub weekly_quota: u64,
+    /// The share (0.0 - 1.0) of `weekly_quota` after which requests are refused.
+    pub max_weekly_share: f64,
+}
+
+impl Default for QuotaGuard {
+    fn default() -> Self {
+        QuotaGuard {
+            weekly_quota: DEFAULT_WEEKLY_QUOTA,
+            max_weekly_share: 0.9,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct QuotaTracker {
+    status: Mutex<QuotaStatus>,
+}
+
+impl QuotaTracker {
+    pub(crate) fn status(&self) -> QuotaStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    pub(crate) fn record(&self, headers: &HeaderMap) {
+        let new_status = QuotaStatus::from_headers(headers);
+        let mut status = self.status.lock().unwrap();
+        // Not every response carries both headers, so keep whatever we knew before
+        if new_status.hourly_used.is_some() {
+            status.hourly_used = new_status.hourly_used;
+        }
+        if new_status.weekly_used.is_some() {
+            status.weekly_used = new_status.weekly_used;
+        }
+        if new_status.updated_at.is_some() {
+            status.updated_at = new_status.updated_at;
+        }
+    }
+
+    pub(crate) fn check(&self, guard: &QuotaGuard) -> Result<(), OpsError> {
+        let status = self.status.lock().unwrap();
+        match status.weekly_share(guard.weekly_quota) {
+            Some(share) if share >= guard.max_weekly_share => Err(OpsError::QuotaExceeded {
+                reason: format!(
+                    "{:.1}% of the weekly quota used, refusing new requests above {:.1}%",
+                    share * 100.0,
+                    guard.max_weekly_share * 100.0
+                ),
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quota_guard() {
+        let tracker = QuotaTracker::default();
+        let guard = QuotaGuard {
+            weekly_quota: 1000,
+            max_weekly_share: 0.5,
+        };
+        // Nothing known yet
+        assert!(tracker.check(&guard).is_ok());
+
+        let mut headers = HeaderMap::new();
+        headers.insert("X-IndividualQuotaPerHour-Used", "120".parse().unwrap());
+        headers.insert("X-RegisteredQuotaPerWeek-Used", "499".parse().unwrap());
+        tracker.record(&headers);
+        assert_eq!(tracker.status().hourly_used, Some(120));
+        assert!(tracker.check(&guard).is_ok());
+
+        let mut headers = HeaderMap::new();
+        headers.insert("X-RegisteredQuotaPerWeek-Used", "500".parse().unwrap());
+        tracker.record(&headers);
+        // The hourly value is kept from the previous response
+        assert_eq!(tracker.status().hourly_used, Some(120));
+        assert_eq!(tracker.status().weekly_used, Some(500));
+        assert!(matches!(
+            tracker.check(&guard),
+            Err(OpsError::QuotaExceeded { .. })
+        ));
+    }
+}
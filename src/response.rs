@@ -0,0 +1,140 @@
+use crate::xml::xml_to_value;
+use crate::{OpsError, QuotaStatus, ThrottlingControl};
+use reqwest::StatusCode;
+use reqwest::header::HeaderMap;
+use serde::de::DeserializeOwned;
+use std::fmt;
+use std::time::Duration;
+
+/// What OPS is asked to answer with. XML is read into the same layout as the JSON, so the typed
+/// results are the same either way, except that XML keeps mixed content (e.g. markup in the full
+/// text) in order.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ResponseFormat {
+    #[default]
+    Json,
+    Xml,
+}
+
+impl fmt::Display for ResponseFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ResponseFormat::Json => write!(f, "application/json"),
+            ResponseFormat::Xml => write!(f, "application/xml"),
+        }
+    }
+}
+
+/// Everything that came back from one request to OPS.
+#[derive(Clone, Debug)]
+pub struct OpsResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
+    pub url: String,
+    /// From sending the request until the whole body was received - time spent waiting for
+    /// throttling isn't included.
+    pub elapsed: Duration,
+    pub throttling: Option<ThrottlingControl>,
+    pub quota: QuotaStatus,
+}
+
+impl OpsResponse {
+    pub(crate) fn new(
+        status: StatusCode,
+        headers: HeaderMap,
+        body: Vec<u8>,
+        url: &str,
+        elapsed: Duration,
+    ) -> OpsResponse {
+        let throttling = headers
+            .get("X-Throttling-Control")
+            .and_then(|header| header.to_str().ok())
+            .and_then(|header| header.parse().ok());
+        let quota = QuotaStatus::from_headers(&headers);
+        OpsResponse {
+            status,
+            headers,
+            body,
+            url: url.to_string(),
+            elapsed,
+            throttling,
+            quota,
+        }
+    }
+
+    pub fn content_type(&self) -> Option<&str> {
+        self.headers
+            .get("Content-Type")
+            .and_then(|v| v.to_str().ok())
+    }
+
+    /// The size of the body in bytes, i.e. roughly what the response cost against the fair use
+    /// quota.
+    pub fn size(&self) -> usize {
+        self.body.len()
+    }
+
+    pub fn text(&self) -> Result<&str, OpsError> {
+        std::str::from_utf8(&self.body).map_err(|e| OpsError::Decode(Box::new(e)))
+    }
+
+    pub fn is_xml(&self) -> bool {
+        self.content_type().is_some_and(|c| c.contains("xml"))
+    }
+
+    /// Deserialises the body. XML bodies are read into the same layout as OPS's JSON first, so
+    /// `T` can be written against the JSON either way.
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, OpsError> {
+        if self.is_xml() {
+            return Ok(serde_json::from_value(xml_to_value(&self.body)?)?);
+        }
+        Ok(serde_json::from_slice(&self.body)?)
+    }
+}
+
+/// A `200 OK` response with the contents of a file in `test/` as the body.
+#[cfg(test)]
+pub(crate) fn fixture_response(path: &str) -> OpsResponse {
+    let body = std::fs::read(path).expect("Couldn't read file");
+    let mut headers = HeaderMap::new();
+    if path.ends_with(".xml") {
+        headers.insert("Content-Type", "application/xml".parse().unwrap());
+    }
+    OpsResponse::new(StatusCode::OK, headers, body, path, Duration::ZERO)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ServiceKind, TrafficLight};
+
+    #[test]
+    fn test_response_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert("Content-Type", "application/json".parse().unwrap());
+        headers.insert(
+            "X-Throttling-Control",
+            "idle (retrieval=green:200, search=green:30)"
+                .parse()
+                .unwrap(),
+        );
+        headers.insert("X-RegisteredQuotaPerWeek-Used", "1048576".parse().unwrap());
+        let response = OpsResponse::new(
+            StatusCode::OK,
+            headers,
+            br#"{"a": 1}"#.to_vec(),
+            "https://ops.epo.org/3.2/rest-services/register/search",
+            Duration::from_millis(250),
+        );
+        assert_eq!(response.content_type(), Some("application/json"));
+        assert_eq!(response.size(), 8);
+        assert_eq!(response.quota.weekly_used, Some(1048576));
+        assert_eq!(
+            response.throttling.as_ref().unwrap().services[&ServiceKind::Search].light,
+            TrafficLight::Green
+        );
+        let json: serde_json::Value = response.json().unwrap();
+        assert_eq!(json["a"], 1);
+    }
+}
@@ -0,0 +1,160 @@
+use crate::OpsError;
+use reqwest::StatusCode;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+/// When and how often failed requests get another go.
+///
+/// Only errors that stand a chance of going away on their own are retried - transport problems,
+/// throttling and the statuses/fault codes listed here. Anything else (e.g. an invalid query or an
+/// unknown number) is returned straight away.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first one. 1 disables retrying.
+    pub max_attempts: u32,
+    /// The delay before the first retry, doubled for every retry after that.
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// Share (0.0 - 1.0) of each delay that's randomised, so parallel jobs don't retry in lockstep.
+    pub jitter: f64,
+    pub retryable_statuses: Vec<StatusCode>,
+    pub retryable_fault_codes: Vec<String>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 4,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+            jitter: 0.5,
+            retryable_statuses: vec![
+                StatusCode::INTERNAL_SERVER_ERROR,
+                StatusCode::BAD_GATEWAY,
+                StatusCode::SERVICE_UNAVAILABLE,
+                StatusCode::GATEWAY_TIMEOUT,
+            ],
+            retryable_fault_codes: vec![
+                "SERVER.LimitedServerResources".to_string(),
+                "SERVER.DomainAccess".to_string(),
+            ],
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that never retries.
+    pub fn none() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 1,
+            ..RetryPolicy::default()
+        }
+    }
+
+    pub(crate) fn is_retryable(&self, error: &OpsError) -> bool {
+        match error {
+            OpsError::Transport(e) => e.is_timeout() || e.is_connect() || e.is_request(),
+            OpsError::Throttled { .. } => true,
+            OpsError::HttpStatus { status, .. } => self.retryable_statuses.contains(status),
+            OpsError::OpsFault { status, code, .. } => {
+                self.retryable_fault_codes.contains(code)
+                    || self.retryable_statuses.contains(status)
+            }
+            // Auth failures already get a re-authentication, the rest won't change by waiting
+            OpsError::Auth { .. }
+            | OpsError::QuotaExceeded { .. }
+            | OpsError::Decode(_)
+            | OpsError::InvalidInput(_)
+            | OpsError::InvalidConfig(_)
+            | OpsError::Io(_) => false,
+        }
+    }
+
+    /// How long to wait before the retry following the failed `attempt` (starting at 1).
+    pub(crate) fn delay(&self, attempt: u32, error: &OpsError) -> Duration {
+        let delay = self.backoff(attempt, random_fraction());
+        match error {
+            OpsError::Throttled {
+                retry_after: Some(retry_after),
+                ..
+            } => delay.max(*retry_after),
+            _ => delay,
+        }
+    }
+
+    fn backoff(&self, attempt: u32, random: f64) -> Duration {
+        let exponential = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)));
+        let capped = exponential.min(self.max_delay);
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        capped.mul_f64(1.0 - jitter * random)
+    }
+}
+
+// Good enough randomness for spreading out retries without pulling in a crate for it -
+//  every RandomState is seeded differently.
+fn random_fraction() -> f64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u8(0);
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retryable_errors() {
+        let policy = RetryPolicy::default();
+        assert!(policy.is_retryable(&OpsError::HttpStatus {
+            status: StatusCode::SERVICE_UNAVAILABLE,
+            body: String::new(),
+        }));
+        assert!(policy.is_retryable(&OpsError::Throttled {
+            retry_after: None,
+            message: String::new(),
+        }));
+        assert!(!policy.is_retryable(&OpsError::OpsFault {
+            status: StatusCode::NOT_FOUND,
+            code: "SERVER.EntityNotFound".to_string(),
+            message: "No results found".to_string(),
+        }));
+        assert!(!policy.is_retryable(&OpsError::OpsFault {
+            status: StatusCode::BAD_REQUEST,
+            code: "CLIENT.InvalidQuery".to_string(),
+            message: String::new(),
+        }));
+        assert!(policy.is_retryable(&OpsError::OpsFault {
+            status: StatusCode::FORBIDDEN,
+            code: "SERVER.LimitedServerResources".to_string(),
+            message: String::new(),
+        }));
+    }
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy {
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(10),
+            jitter: 0.5,
+            ..RetryPolicy::default()
+        };
+        assert_eq!(policy.backoff(1, 0.0), Duration::from_secs(1));
+        assert_eq!(policy.backoff(3, 0.0), Duration::from_secs(4));
+        assert_eq!(policy.backoff(3, 1.0), Duration::from_secs(2));
+        assert_eq!(policy.backoff(20, 0.0), Duration::from_secs(10));
+
+        let throttled = OpsError::Throttled {
+            retry_after: Some(Duration::from_secs(30)),
+            message: String::new(),
+        };
+        assert_eq!(policy.delay(1, &throttled), Duration::from_secs(30));
+
+        for _ in 0..100 {
+            let r = random_fraction();
+            assert!((0.0..1.0).contains(&r));
+        }
+    }
+}
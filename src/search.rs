@@ -0,0 +1,318 @@
+use crate::deser::{DocumentId, OneOrMany, preferred_document_id};
+use crate::exchange::{Exchange, Pages};
+use crate::ops_client::{ClientCore, OpsRequest, encode_input};
+use crate::{
+    OpsError, OpsResponse, PatentDetails, PatentReferenceType, PublicationConstituents, ServiceKind,
+};
+use log::debug;
+use reqwest::StatusCode;
+use reqwest::header::HeaderMap;
+use serde::Deserialize;
+use serde_json::Value;
+
+/// OPS won't return anything past the 2000th result of a published-data search.
+pub const MAX_PUBLISHED_SEARCH_RESULTS: u32 = 2000;
+
+/// The results of a published-data search - section 3.1.4 of the EPO OPS doc.
+#[derive(Debug)]
+pub struct PublishedSearchResults {
+    /// How many publications match, which can be more than could be fetched (see
+    /// `MAX_PUBLISHED_SEARCH_RESULTS`).
+    pub total_count: u32,
+    pub results: Vec<PublishedSearchResult>,
+}
+
+#[derive(Debug)]
+pub struct PublishedSearchResult {
+    pub family_id: Option<String>,
+    pub publication: PatentDetails,
+    /// The `exchange-document`, only there when constituents were asked for.
+    pub biblio: Option<Value>,
+}
+
+/* Published-data search structs
+ *  Without constituents the results are publication-references, with them exchange-documents.
+ */
+
+#[derive(Debug, Deserialize)]
+struct SearchPublicationReference {
+    #[serde(rename(deserialize = "@family-id"))]
+    family_id: Option<String>,
+    #[serde(rename(deserialize = "document-id"))]
+    document_id: OneOrMany<DocumentId>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchExchangeDocuments {
+    #[serde(rename(deserialize = "exchange-document"), default)]
+    exchange_document: OneOrMany<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchSearchResult {
+    #[serde(rename(deserialize = "ops:publication-reference"), default)]
+    ops_publication_reference: OneOrMany<SearchPublicationReference>,
+    #[serde(rename(deserialize = "exchange-documents"), default)]
+    exchange_documents: OneOrMany<SearchExchangeDocuments>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchBiblioSearch {
+    #[serde(rename(deserialize = "@total-result-count"))]
+    total_result_count: String,
+    // Missing when nothing matched
+    #[serde(rename(deserialize = "ops:search-result"))]
+    ops_search_result: Option<SearchSearchResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchWorldPatentData {
+    #[serde(rename(deserialize = "ops:biblio-search"))]
+    ops_biblio_search: SearchBiblioSearch,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchResult {
+    #[serde(rename(deserialize = "ops:world-patent-data"))]
+    ops_world_patent_data: SearchWorldPatentData,
+}
+
+fn exchange_document_result(exchange_document: Value) -> Option<PublishedSearchResult> {
+    let attribute = |name: &str| {
+        exchange_document
+            .get(name)
+            .and_then(Value::as_str)
+            .map(str::to_string)
+    };
+    let publication = PatentDetails {
+        country_code: attribute("@country")?,
+        number: attribute("@doc-number")?,
+        kind_code: attribute("@kind"),
+        date: None,
+        reference_type: PatentReferenceType::Publication,
+    };
+    Some(PublishedSearchResult {
+        family_id: attribute("@family-id"),
+        publication,
+        biblio: Some(exchange_document),
+    })
+}
+
+/// One page of results.
+pub(crate) fn parse_published_search(
+    response: &OpsResponse,
+) -> Result<PublishedSearchResults, OpsError> {
+    let result: SearchResult = response.json()?;
+    let biblio_search = result.ops_world_patent_data.ops_biblio_search;
+    let total_count = biblio_search
+        .total_result_count
+        .trim()
+        .parse()
+        .map_err(|_| {
+            OpsError::Decode(
+                format!(
+                    "Couldn't parse @total-result-count \"{}\"",
+                    biblio_search.total_result_count
+                )
+                .into(),
+            )
+        })?;
+    debug!(target: "search", "Found {} results", total_count);
+
+    let mut results = Vec::new();
+    if let Some(search_result) = biblio_search.ops_search_result {
+        for reference in search_result.ops_publication_reference.into_vec() {
+            if let Some(publication) =
+                preferred_document_id(reference.document_id, PatentReferenceType::Publication)
+            {
+                results.push(PublishedSearchResult {
+                    family_id: reference.family_id,
+                    publication,
+                    biblio: None,
+                });
+            }
+        }
+        for exchange_documents in search_result.exchange_documents.into_vec() {
+            results.extend(
+                exchange_documents
+                    .exchange_document
+                    .into_vec()
+                    .into_iter()
+                    .filter_map(exchange_document_result),
+            );
+        }
+    }
+    Ok(PublishedSearchResults {
+        total_count,
+        results,
+    })
+}
+
+/// OPS answers a search without any hits with a "not found" fault.
+fn is_no_results(error: &OpsError) -> bool {
+    matches!(error, OpsError::OpsFault { status, code, .. }
+        if *status == StatusCode::NOT_FOUND && code.contains("EntityNotFound"))
+}
+
+/// Every page of a published-data search read into one set of results. No pages at all (a search
+/// without any hits) is an empty result.
+pub(crate) fn parse_published_pages(
+    pages: &[OpsResponse],
+) -> Result<PublishedSearchResults, OpsError> {
+    let mut results = PublishedSearchResults {
+        total_count: 0,
+        results: Vec::new(),
+    };
+    for (i, page) in pages.iter().enumerate() {
+        let page = parse_published_search(page)?;
+        if i == 0 {
+            results.total_count = page.total_count;
+        }
+        results.results.extend(page.results);
+    }
+    Ok(results)
+}
+
+impl ClientCore {
+    /// The request for one page (e.g. "1-100") of a published-data search.
+    pub(crate) fn published_search_request(
+        &self,
+        query_string: &str,
+        constituents: &[PublicationConstituents],
+        range: &str,
+    ) -> Result<OpsRequest, OpsError> {
+        let mut url = format!(
+            "{}/search",
+            self.settings.endpoints.service_url("published-data")
+        );
+        for (i, constituent) in constituents.iter().enumerate() {
+            match constituent {
+                PublicationConstituents::Biblio
+                | PublicationConstituents::Abstract
+                | PublicationConstituents::FullCycle => {}
+                _ => {
+                    return Err(OpsError::InvalidInput(format!(
+                        "Can't get {} with a published-data search",
+                        constituent
+                    )));
+                }
+            }
+            url.push(if i == 0 { '/' } else { ',' });
+            url.push_str(&constituent.to_string());
+        }
+        let mut headers = HeaderMap::new();
+        headers.insert("Content-Type", "text/plain".parse().unwrap());
+        headers.insert("Accept", self.accept());
+        headers.insert("Range", range.parse().unwrap());
+        let mut data = "q=".to_string();
+        data.push_str(&encode_input(query_string));
+        debug!(target: "search", "Running search with query {}, range {}", data, range);
+        Ok(OpsRequest::post(
+            &url,
+            Some(data),
+            headers,
+            ServiceKind::Search,
+        ))
+    }
+
+    pub(crate) fn search_published_response<'a>(
+        &'a self,
+        query_string: &'a str,
+        constituents: &'a [PublicationConstituents],
+    ) -> Pages<'a> {
+        Pages::new(
+            "search",
+            move |range| self.published_search_request(query_string, constituents, range),
+            |response| {
+                let total = parse_published_search(response)?.total_count;
+                Ok(total.min(MAX_PUBLISHED_SEARCH_RESULTS))
+            },
+        )
+        .or_none_when(is_no_results)
+    }
+
+    pub(crate) fn search_published<'a>(
+        &'a self,
+        query_string: &'a str,
+        constituents: &'a [PublicationConstituents],
+    ) -> impl Exchange<Output = PublishedSearchResults> + 'a {
+        self.search_published_response(query_string, constituents)
+            .map(|pages| parse_published_pages(&pages))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ops_client::test_core;
+    use crate::response::fixture_response;
+
+    #[test]
+    fn test_published_search_deser() {
+        let page = parse_published_search(&fixture_response("test/example_published_search.json"))
+            .unwrap();
+        assert_eq!(page.total_count, 153);
+        assert_eq!(page.results.len(), 2);
+        assert_eq!(page.results[0].family_id.as_deref(), Some("54065453"));
+        assert_eq!(page.results[0].publication.to_string(), "US2017032346A1");
+        assert!(page.results[0].biblio.is_none());
+
+        let page = parse_published_search(&fixture_response(
+            "test/example_published_search_biblio.json",
+        ))
+        .unwrap();
+        assert_eq!(page.total_count, 1);
+        assert_eq!(page.results[0].publication.to_string(), "EP3000000A1");
+        assert!(page.results[0].biblio.is_some());
+
+        let core = test_core();
+        let mut search = core.search_published("pa=nobody", &[]);
+        assert!(search.next_request().unwrap().is_some());
+        search
+            .handle(Err(OpsError::OpsFault {
+                status: StatusCode::NOT_FOUND,
+                code: "SERVER.EntityNotFound".to_string(),
+                message: "No results found".to_string(),
+            }))
+            .unwrap();
+        assert!(search.next_request().unwrap().is_none());
+        let no_hits = search.finish().unwrap();
+        assert_eq!(no_hits.total_count, 0);
+        assert!(no_hits.results.is_empty());
+
+        let mut search = core.search_published("pa=nobody", &[]);
+        search.next_request().unwrap();
+        assert!(
+            search
+                .handle(Err(OpsError::InvalidInput(String::new())))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_published_search_request() {
+        let core = test_core();
+        let request = core
+            .published_search_request(
+                "ti=plastic and pa=ibm",
+                &[
+                    PublicationConstituents::Biblio,
+                    PublicationConstituents::Abstract,
+                ],
+                "1-100",
+            )
+            .unwrap();
+        assert_eq!(
+            request.url,
+            "https://ops.epo.org/3.2/rest-services/published-data/search/biblio,abstract"
+        );
+        assert_eq!(
+            request.body.as_deref(),
+            Some("q=ti%3Dplastic%20and%20pa%3Dibm")
+        );
+        assert!(
+            core.published_search_request("pa=ibm", &[PublicationConstituents::Claims], "1-100")
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,272 @@
+use crate::OpsError;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The overall state OPS reports itself to be in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SystemState {
+    Idle,
+    Busy,
+    Overloaded,
+}
+
+/// The per service traffic light. Black means the service is refusing requests from us.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TrafficLight {
+    Green,
+    Yellow,
+    Red,
+    Black,
+}
+
+/// The groups of services OPS throttles separately (section 2.2.4 of the EPO OPS doc).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ServiceKind {
+    Retrieval,
+    Search,
+    Inpadoc,
+    Images,
+    Other,
+}
+
+impl fmt::Display for ServiceKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ServiceKind::Retrieval => write!(f, "retrieval"),
+            ServiceKind::Search => write!(f, "search"),
+            ServiceKind::Inpadoc => write!(f, "inpadoc"),
+            ServiceKind::Images => write!(f, "images"),
+            ServiceKind::Other => write!(f, "other"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ServiceThrottle {
+    pub light: TrafficLight,
+    pub requests_per_minute: u32,
+}
+
+/// The parsed contents of an `X-Throttling-Control` header, which looks like
+/// `busy (images=green:100, inpadoc=yellow:45, other=green:1000, retrieval=green:200, search=red:5)`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ThrottlingControl {
+    pub system_state: SystemState,
+    pub services: BTreeMap<ServiceKind, ServiceThrottle>,
+}
+
+fn decode_error(header: &str) -> OpsError {
+    OpsError::Decode(format!("Couldn't parse X-Throttling-Control header \"{}\"", header).into())
+}
+
+impl FromStr for ThrottlingControl {
+    type Err = OpsError;
+
+    fn from_str(header: &str) -> Result<Self, Self::Err> {
+        let (state, services_str) = header.split_once('(').ok_or_else(|| decode_error(header))?;
+        let system_state = match state.trim() {
+            "idle" => SystemState::Idle,
+            "busy" => SystemState::Busy,
+            "overloaded" => SystemState::Overloaded,
+            _ => return Err(decode_error(header)),
+        };
+
+        let mut services = BTreeMap::new();
+        for service in services_str.trim_end().trim_end_matches(')').split(',') {
+            let (name, throttle) = service
+                .split_once('=')
+                .ok_or_else(|| decode_error(header))?;
+            let (light, requests_per_minute) = throttle
+                .split_once(':')
+                .ok_or_else(|| decode_error(header))?;
+            let service_kind = match name.trim() {
+                "retrieval" => ServiceKind::Retrieval,
+                "search" => ServiceKind::Search,
+                "inpadoc" => ServiceKind::Inpadoc,
+                "images" => ServiceKind::Images,
+                "other" => ServiceKind::Other,
+                // Don't want a new service appearing to break everything else
+                _ => continue,
+            };
+            let light = match light.trim() {
+                "green" => TrafficLight::Green,
+                "yellow" => TrafficLight::Yellow,
+                "red" => TrafficLight::Red,
+                "black" => TrafficLight::Black,
+                _ => return Err(decode_error(header)),
+            };
+            let requests_per_minute = requests_per_minute
+                .trim()
+                .parse()
+                .map_err(|_| decode_error(header))?;
+            services.insert(
+                service_kind,
+                ServiceThrottle {
+                    light,
+                    requests_per_minute,
+                },
+            );
+        }
+        Ok(ThrottlingControl {
+            system_state,
+            services,
+        })
+    }
+}
+
+// How long to stay away from a service that's gone black when OPS doesn't say otherwise
+const BLACK_BACK_OFF: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Default)]
+struct ServiceSchedule {
+    interval: Duration,
+    next_allowed: Option<Instant>,
+}
+
+/// Spaces out requests to each service according to the last throttling state OPS reported.
+#[derive(Debug, Default)]
+pub(crate) struct Throttler {
+    schedules: Mutex<BTreeMap<ServiceKind, ServiceSchedule>>,
+}
+
+impl Throttler {
+    /// Claims the next slot for `service`, returning how long to wait before sending.
+    pub(crate) fn reserve(&self, service: ServiceKind) -> Duration {
+        self.reserve_at(service, Instant::now())
+    }
+
+    fn reserve_at(&self, service: ServiceKind, now: Instant) -> Duration {
+        let mut schedules = self.schedules.lock().unwrap();
+        let schedule = schedules.entry(service).or_default();
+        let start = schedule.next_allowed.map_or(now, |t| t.max(now));
+        schedule.next_allowed = Some(start + schedule.interval);
+        start - now
+    }
+
+    pub(crate) fn update(&self, control: &ThrottlingControl) {
+        self.update_at(control, Instant::now())
+    }
+
+    fn update_at(&self, control: &ThrottlingControl, now: Instant) {
+        let mut schedules = self.schedules.lock().unwrap();
+        for (service, throttle) in &control.services {
+            let schedule = schedules.entry(*service).or_default();
+            if throttle.light == TrafficLight::Black || throttle.requests_per_minute == 0 {
+                let blocked_until = now + BLACK_BACK_OFF;
+                schedule.next_allowed = Some(
+                    schedule
+                        .next_allowed
+                        .map_or(blocked_until, |t| t.max(blocked_until)),
+                );
+                continue;
+            }
+            let mut interval = Duration::from_secs(60) / throttle.requests_per_minute;
+            // Slow down further than the allowed rate when OPS is struggling, rather than
+            //  running right up to the limit and getting ourselves blocked
+            interval *= match throttle.light {
+                TrafficLight::Green => 1,
+                TrafficLight::Yellow => 2,
+                _ => 4,
+            };
+            if control.system_state == SystemState::Overloaded {
+                interval *= 2;
+            }
+            schedule.interval = interval;
+        }
+    }
+
+    /// Keeps everyone away from `service` after OPS rejected a request to it.
+    pub(crate) fn back_off(&self, service: ServiceKind, retry_after: Option<Duration>) {
+        self.back_off_at(service, retry_after, Instant::now())
+    }
+
+    fn back_off_at(&self, service: ServiceKind, retry_after: Option<Duration>, now: Instant) {
+        let mut schedules = self.schedules.lock().unwrap();
+        let schedule = schedules.entry(service).or_default();
+        let blocked_until = now + retry_after.unwrap_or(BLACK_BACK_OFF);
+        schedule.next_allowed = Some(
+            schedule
+                .next_allowed
+                .map_or(blocked_until, |t| t.max(blocked_until)),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_throttling_control() {
+        let control: ThrottlingControl = "busy (images=green:100, inpadoc=yellow:45, other=green:1000, retrieval=green:200, search=black:0)"
+            .parse()
+            .unwrap();
+        assert_eq!(control.system_state, SystemState::Busy);
+        assert_eq!(
+            control.services[&ServiceKind::Inpadoc],
+            ServiceThrottle {
+                light: TrafficLight::Yellow,
+                requests_per_minute: 45
+            }
+        );
+        assert_eq!(
+            control.services[&ServiceKind::Search].light,
+            TrafficLight::Black
+        );
+        assert_eq!(control.services.len(), 5);
+
+        assert!("".parse::<ThrottlingControl>().is_err());
+        assert!(
+            "sleepy (search=green:10)"
+                .parse::<ThrottlingControl>()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_pacing() {
+        let throttler = Throttler::default();
+        let now = Instant::now();
+        // Nothing known yet, so no waiting
+        assert_eq!(
+            throttler.reserve_at(ServiceKind::Search, now),
+            Duration::ZERO
+        );
+
+        let control: ThrottlingControl =
+            "idle (retrieval=green:120, search=yellow:30, images=black:0)"
+                .parse()
+                .unwrap();
+        throttler.update_at(&control, now);
+        assert_eq!(
+            throttler.reserve_at(ServiceKind::Retrieval, now),
+            Duration::ZERO
+        );
+        assert_eq!(
+            throttler.reserve_at(ServiceKind::Retrieval, now),
+            Duration::from_millis(500)
+        );
+        // Yellow doubles the 2s interval
+        assert_eq!(
+            throttler.reserve_at(ServiceKind::Search, now),
+            Duration::ZERO
+        );
+        assert_eq!(
+            throttler.reserve_at(ServiceKind::Search, now),
+            Duration::from_secs(4)
+        );
+        assert_eq!(
+            throttler.reserve_at(ServiceKind::Images, now),
+            BLACK_BACK_OFF
+        );
+
+        throttler.back_off_at(ServiceKind::Other, Some(Duration::from_secs(10)), now);
+        assert_eq!(
+            throttler.reserve_at(ServiceKind::Other, now),
+            Duration::from_secs(10)
+        );
+    }
+}
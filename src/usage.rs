@@ -0,0 +1,268 @@
+use crate::exchange::Exchange;
+use crate::ops_client::{ClientCore, parse_usage};
+use crate::{OpsError, OpsResponse, Usage};
+use chrono::{DateTime, Days, NaiveDate};
+use std::collections::BTreeMap;
+use std::ops::{Add, AddAssign};
+
+// The metrics the usage statistics are reported in
+const MESSAGE_COUNT_METRIC: &str = "message_count";
+const RESPONSE_SIZE_METRIC: &str = "total_response_size";
+
+/// How many requests were made and how much they downloaded.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct UsageCounts {
+    pub messages: u64,
+    pub response_bytes: u64,
+}
+
+impl Add for UsageCounts {
+    type Output = UsageCounts;
+
+    fn add(self, other: UsageCounts) -> UsageCounts {
+        UsageCounts {
+            messages: self.messages + other.messages,
+            response_bytes: self.response_bytes + other.response_bytes,
+        }
+    }
+}
+
+impl AddAssign for UsageCounts {
+    fn add_assign(&mut self, other: UsageCounts) {
+        *self = *self + other;
+    }
+}
+
+/// The usage of one service (a dimension of the usage statistics) on one day.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UsageEntry {
+    pub date: NaiveDate,
+    pub service: String,
+    pub counts: UsageCounts,
+}
+
+/// How the weekly fair use quota is holding up, going by a `UsageReport`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct QuotaProjection {
+    /// The weekly allowance compared against, in bytes.
+    pub weekly_quota: u64,
+    /// The bytes downloaded in the last 7 days of the report (fewer if the report is shorter).
+    pub weekly_used: u64,
+    /// `weekly_used` as a share (0.0 - 1.0+) of `weekly_quota`.
+    pub weekly_share: f64,
+    /// The average bytes downloaded per day over the same days as `weekly_used`.
+    pub daily_rate: f64,
+    /// The day the rest of the quota runs out if downloading carries on at `daily_rate`, `None`
+    /// if nothing is being downloaded.
+    pub exhausted_on: Option<NaiveDate>,
+}
+
+/// The usage statistics for a date range as numbers, per day and per service.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UsageReport {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    /// Sorted by date, then service.
+    pub entries: Vec<UsageEntry>,
+}
+
+fn parse_number(value: &str) -> Result<u64, OpsError> {
+    // The values come as floats in strings, e.g. "1234.0"
+    value
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|v| v.is_finite() && *v >= 0.0)
+        .map(|v| v.round() as u64)
+        .ok_or_else(|| OpsError::Decode(format!("Invalid usage value {:?}", value).into()))
+}
+
+impl UsageReport {
+    /// Totals up the raw usage statistics for `from` to `to` (inclusive), leaving out anything
+    /// outside of that. The same service on the same day in different environments is added
+    /// together.
+    pub fn from_usage(usage: &Usage, from: NaiveDate, to: NaiveDate) -> Result<Self, OpsError> {
+        let mut counts: BTreeMap<(NaiveDate, String), UsageCounts> = BTreeMap::new();
+        let dimensions = usage
+            .environments
+            .iter()
+            .flat_map(|environment| &environment.dimensions);
+        for dimension in dimensions {
+            for metric in &dimension.metrics {
+                for value in &metric.values {
+                    let date = DateTime::from_timestamp_millis(value.timestamp)
+                        .ok_or_else(|| {
+                            OpsError::Decode(
+                                format!("Invalid usage timestamp {}", value.timestamp).into(),
+                            )
+                        })?
+                        .date_naive();
+                    if date < from || date > to {
+                        continue;
+                    }
+                    let number = parse_number(&value.value)?;
+                    let entry = counts.entry((date, dimension.name.clone())).or_default();
+                    match metric.name.as_str() {
+                        MESSAGE_COUNT_METRIC => entry.messages += number,
+                        RESPONSE_SIZE_METRIC => entry.response_bytes += number,
+                        _ => {}
+                    }
+                }
+            }
+        }
+        Ok(UsageReport {
+            from,
+            to,
+            entries: counts
+                .into_iter()
+                .map(|((date, service), counts)| UsageEntry {
+                    date,
+                    service,
+                    counts,
+                })
+                .collect(),
+        })
+    }
+
+    /// The number of days from `from` to `to`, inclusive.
+    pub fn days(&self) -> u64 {
+        (self.to - self.from).num_days().max(0) as u64 + 1
+    }
+
+    pub fn totals(&self) -> UsageCounts {
+        self.entries
+            .iter()
+            .fold(UsageCounts::default(), |total, entry| total + entry.counts)
+    }
+
+    /// Every service added together, per day. Days without any usage are left out.
+    pub fn by_day(&self) -> BTreeMap<NaiveDate, UsageCounts> {
+        let mut days = BTreeMap::new();
+        for entry in &self.entries {
+            *days.entry(entry.date).or_default() += entry.counts;
+        }
+        days
+    }
+
+    /// Every day added together, per service.
+    pub fn by_service(&self) -> BTreeMap<String, UsageCounts> {
+        let mut services = BTreeMap::new();
+        for entry in &self.entries {
+            *services.entry(entry.service.clone()).or_default() += entry.counts;
+        }
+        services
+    }
+
+    /// Compares the last 7 days of the report against `weekly_quota` (e.g. `DEFAULT_WEEKLY_QUOTA`
+    /// or `QuotaGuard::weekly_quota`) and projects when the rest of it runs out.
+    ///
+    /// This is only an estimate - it assumes the report ends today and that nothing drops out of
+    /// the week in the meantime.
+    pub fn quota_projection(&self, weekly_quota: u64) -> QuotaProjection {
+        let week_start = self
+            .to
+            .checked_sub_days(Days::new(6))
+            .unwrap_or(self.to)
+            .max(self.from);
+        let week_days = (self.to - week_start).num_days().max(0) as u64 + 1;
+        let weekly_used = self
+            .entries
+            .iter()
+            .filter(|entry| entry.date >= week_start && entry.date <= self.to)
+            .map(|entry| entry.counts.response_bytes)
+            .sum::<u64>();
+        let daily_rate = weekly_used as f64 / week_days as f64;
+        let exhausted_on = if weekly_used >= weekly_quota {
+            Some(self.to)
+        } else if daily_rate > 0.0 {
+            let days_left = ((weekly_quota - weekly_used) as f64 / daily_rate).ceil() as u64;
+            self.to.checked_add_days(Days::new(days_left))
+        } else {
+            None
+        };
+        QuotaProjection {
+            weekly_quota,
+            weekly_used,
+            weekly_share: weekly_used as f64 / weekly_quota as f64,
+            daily_rate,
+            exhausted_on,
+        }
+    }
+}
+
+pub(crate) fn parse_usage_report(
+    response: &OpsResponse,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> Result<UsageReport, OpsError> {
+    UsageReport::from_usage(&parse_usage(response)?, from, to)
+}
+
+impl ClientCore {
+    /// Built on the same response as `get_usage_data`.
+    pub(crate) fn get_usage_report(
+        &self,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> impl Exchange<Output = UsageReport> {
+        self.get_usage_data_response(from, to)
+            .map(move |response| parse_usage_report(&response, from, to))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::response::fixture_response;
+
+    #[test]
+    fn test_usage_report() {
+        let response = fixture_response("test/example_usage.json");
+        let from = NaiveDate::from_ymd_opt(2025, 10, 20).unwrap();
+        let to = NaiveDate::from_ymd_opt(2025, 10, 23).unwrap();
+        let report = parse_usage_report(&response, from, to).unwrap();
+        assert_eq!(report.entries.len(), 3);
+        assert_eq!(report.days(), 4);
+        assert_eq!(
+            report.totals(),
+            UsageCounts {
+                messages: 212,
+                response_bytes: 3670016
+            }
+        );
+
+        let days = report.by_day();
+        assert_eq!(
+            days[&NaiveDate::from_ymd_opt(2025, 10, 22).unwrap()],
+            UsageCounts {
+                messages: 92,
+                response_bytes: 2621440
+            }
+        );
+        let services = report.by_service();
+        assert_eq!(services["published-data"].messages, 200);
+        assert_eq!(services["register"].response_bytes, 524288);
+
+        // 3.5 MiB over 4 days, with 4 MiB allowed leaves 0.5 MiB
+        let projection = report.quota_projection(4 * 1024 * 1024);
+        assert_eq!(projection.weekly_used, 3670016);
+        assert_eq!(projection.daily_rate, 917504.0);
+        assert_eq!(
+            projection.exhausted_on,
+            NaiveDate::from_ymd_opt(2025, 10, 24)
+        );
+        assert_eq!(report.quota_projection(1024).exhausted_on, Some(report.to));
+
+        // Only the 22nd, and the rate is over the 7 days up to it rather than the whole fortnight
+        let day = NaiveDate::from_ymd_opt(2025, 10, 22).unwrap();
+        let report = parse_usage_report(&response, day, day).unwrap();
+        assert_eq!(report.entries.len(), 2);
+        assert_eq!(report.totals().response_bytes, 2621440);
+        let from = NaiveDate::from_ymd_opt(2025, 10, 10).unwrap();
+        let report = parse_usage_report(&response, from, to).unwrap();
+        assert_eq!(
+            report.quota_projection(4 * 1024 * 1024).daily_rate,
+            524288.0
+        );
+    }
+}
@@ -0,0 +1,272 @@
+use crate::OpsError;
+use quick_xml::Reader;
+use quick_xml::escape::resolve_predefined_entity;
+use quick_xml::events::{BytesStart, Event};
+use serde_json::{Map, Value};
+
+/// Where the text of an element with mixed content (e.g. a paragraph with `<sub>` in it) is kept
+/// in document order - OPS's own JSON only has the loose text under `$`, out of order.
+pub(crate) const MIXED_TEXT_KEY: &str = "#text";
+
+/* XML responses
+ *  These are read into the same layout OPS uses when it renders its XML as JSON - attributes as
+ *  "@name", text as "$", namespace prefixes kept, repeated elements as arrays - so the same
+ *  deserialisation works for both. Elements the OPS schemas allow more than one of (REPEATED)
+ *  are always arrays, even when there's only one; OPS's JSON doesn't do that, so the models still
+ *  take OneOrMany.
+ */
+
+// (element it's in, element) for the elements that can repeat - "*" for anywhere
+const REPEATED: &[(&str, &str)] = &[
+    ("*", "document-id"),
+    ("*", "priority-claim"),
+    ("*", "abstract"),
+    ("*", "p"),
+    ("*", "claim-text"),
+    ("claims", "claim"),
+    ("ftxt:fulltext-document", "description"),
+    ("ftxt:fulltext-document", "claims"),
+    ("*", "ftxt:fulltext-document"),
+    ("ops:world-patent-data", "exchange-documents"),
+    ("ops:search-result", "exchange-documents"),
+    ("exchange-documents", "exchange-document"),
+    ("*", "ops:publication-reference"),
+    ("*", "ops:family-member"),
+    ("*", "ops:legal"),
+    ("*", "ops:inquiry-result"),
+    ("*", "ops:fulltext-instance"),
+    ("*", "ops:fulltext-format"),
+    ("*", "ops:document-instance"),
+    ("*", "ops:document-format"),
+    ("*", "ops:document-section"),
+    ("*", "ops:mapping"),
+    ("*", "ops:classification-statistics"),
+    ("*", "cpc:classification-item"),
+    ("*", "classification-item"),
+];
+
+fn repeats(parent: &str, name: &str) -> bool {
+    REPEATED
+        .iter()
+        .any(|&(p, n)| n == name && (p == "*" || p == parent))
+}
+
+struct Element {
+    name: String,
+    fields: Map<String, Value>,
+    // Text directly inside this element
+    text: String,
+    // All of the text inside this element, including its children's, in order
+    full_text: String,
+    has_children: bool,
+}
+
+fn xml_error<E: std::error::Error + Send + Sync + 'static>(e: E) -> OpsError {
+    OpsError::Decode(Box::new(e))
+}
+
+impl Element {
+    fn new(name: String) -> Element {
+        Element {
+            name,
+            fields: Map::new(),
+            text: String::new(),
+            full_text: String::new(),
+            has_children: false,
+        }
+    }
+
+    fn start(start: &BytesStart, reader: &Reader<&[u8]>) -> Result<Element, OpsError> {
+        let name = std::str::from_utf8(start.name().as_ref())
+            .map_err(xml_error)?
+            .to_string();
+        let mut element = Element::new(name);
+        for attribute in start.attributes() {
+            let attribute = attribute.map_err(xml_error)?;
+            let key = std::str::from_utf8(attribute.key.as_ref()).map_err(xml_error)?;
+            // The namespace declarations don't tell the models anything
+            if key == "xmlns" || key.starts_with("xmlns:") {
+                continue;
+            }
+            let value = attribute
+                .decode_and_unescape_value(reader.decoder())
+                .map_err(xml_error)?;
+            element
+                .fields
+                .insert(format!("@{}", key), Value::String(value.into_owned()));
+        }
+        Ok(element)
+    }
+
+    fn push_text(&mut self, text: &str) {
+        self.text.push_str(text);
+        self.full_text.push_str(text);
+    }
+
+    fn push_child(&mut self, child: Element) {
+        self.has_children = true;
+        self.full_text.push_str(&child.full_text);
+        let (name, value) = child.finish();
+        match self.fields.get_mut(&name) {
+            Some(Value::Array(values)) => values.push(value),
+            Some(existing) => {
+                let first = existing.take();
+                *existing = Value::Array(vec![first, value]);
+            }
+            None if repeats(&self.name, &name) => {
+                self.fields.insert(name, Value::Array(vec![value]));
+            }
+            None => {
+                self.fields.insert(name, value);
+            }
+        }
+    }
+
+    fn finish(mut self) -> (String, Value) {
+        let has_text = !self.text.trim().is_empty();
+        if has_text || (!self.has_children && !self.text.is_empty()) {
+            self.fields
+                .insert("$".to_string(), Value::String(self.text.clone()));
+        }
+        if has_text && self.has_children {
+            self.fields
+                .insert(MIXED_TEXT_KEY.to_string(), Value::String(self.full_text));
+        }
+        (self.name, Value::Object(self.fields))
+    }
+}
+
+/// Reads an XML body into a `Value` laid out like OPS's JSON for the same response.
+pub(crate) fn xml_to_value(body: &[u8]) -> Result<Value, OpsError> {
+    let mut reader = Reader::from_reader(body);
+    // Stands in for the document, so the root element ends up as its only field
+    let mut stack = vec![Element::new(String::new())];
+    loop {
+        match reader.read_event().map_err(xml_error)? {
+            Event::Start(start) => stack.push(Element::start(&start, &reader)?),
+            Event::Empty(start) => {
+                let element = Element::start(&start, &reader)?;
+                current(&mut stack)?.push_child(element);
+            }
+            Event::End(_) => {
+                let element = stack.pop().filter(|_| !stack.is_empty()).ok_or_else(|| {
+                    OpsError::Decode("Closing tag without an opening one in the XML".into())
+                })?;
+                current(&mut stack)?.push_child(element);
+            }
+            Event::Text(text) => {
+                let text = text.xml_content().map_err(xml_error)?;
+                current(&mut stack)?.push_text(&text);
+            }
+            Event::CData(data) => {
+                let data = data.decode().map_err(xml_error)?;
+                current(&mut stack)?.push_text(&data);
+            }
+            Event::GeneralRef(reference) => {
+                let text = match reference.resolve_char_ref().map_err(xml_error)? {
+                    Some(c) => c.to_string(),
+                    None => {
+                        let name = reference.decode().map_err(xml_error)?;
+                        resolve_predefined_entity(&name)
+                            .ok_or_else(|| {
+                                OpsError::Decode(format!("Unknown XML entity &{};", name).into())
+                            })?
+                            .to_string()
+                    }
+                };
+                current(&mut stack)?.push_text(&text);
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    match stack.pop() {
+        Some(document) if stack.is_empty() => Ok(Value::Object(document.fields)),
+        _ => Err(OpsError::Decode("Unclosed element in the XML".into())),
+    }
+}
+
+fn current(stack: &mut [Element]) -> Result<&mut Element, OpsError> {
+    stack
+        .last_mut()
+        .ok_or_else(|| OpsError::Decode("Content outside of the root element".into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fulltext::parse_description;
+    use crate::response::fixture_response;
+    use serde_json::json;
+
+    #[test]
+    fn test_xml_to_value() {
+        let xml = br#"<?xml version="1.0" encoding="UTF-8"?>
+<ops:world-patent-data xmlns="http://www.epo.org/exchange" xmlns:ops="http://ops.epo.org">
+    <ops:inquiry-result>
+        <document-id document-id-type="epodoc"><doc-number>EP1000000A1</doc-number></document-id>
+        <document-id document-id-type="docdb">
+            <country>EP</country>
+            <doc-number>1000000</doc-number>
+        </document-id>
+        <p num="0001">Water (H<sub>2</sub>O) &amp; salt&#x21;</p>
+        <empty/>
+        <claims><claim><claim-text>1. A brick.</claim-text></claim></claims>
+    </ops:inquiry-result>
+</ops:world-patent-data>"#;
+        let value = xml_to_value(xml).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "ops:world-patent-data": {
+                    "ops:inquiry-result": [{
+                        "document-id": [
+                            {"@document-id-type": "epodoc", "doc-number": {"$": "EP1000000A1"}},
+                            {
+                                "@document-id-type": "docdb",
+                                "country": {"$": "EP"},
+                                "doc-number": {"$": "1000000"}
+                            }
+                        ],
+                        "p": [{
+                            "@num": "0001",
+                            "$": "Water (HO) & salt!",
+                            "sub": {"$": "2"},
+                            "#text": "Water (H2O) & salt!"
+                        }],
+                        "empty": {},
+                        "claims": {
+                            "claim": [{"claim-text": [{"$": "1. A brick."}]}]
+                        }
+                    }]
+                }
+            })
+        );
+        assert!(xml_to_value(b"<a><b></a>").is_err());
+
+        // A repeatable element on its own is still a list
+        let value = xml_to_value(b"<a><document-id><doc-number>1</doc-number></document-id></a>");
+        assert_eq!(
+            value.unwrap(),
+            json!({"a": {"document-id": [{"doc-number": {"$": "1"}}]}})
+        );
+    }
+
+    #[test]
+    fn test_xml_response() {
+        let response = fixture_response("test/example_description.xml");
+        assert!(response.is_xml());
+        let descriptions = parse_description(&response).unwrap();
+        let description = &descriptions[0];
+        assert_eq!(
+            description.publication.as_ref().unwrap().to_string(),
+            "EP1000000B1"
+        );
+        assert_eq!(description.language.as_deref(), Some("EN"));
+        assert_eq!(description.paragraphs.len(), 2);
+        assert_eq!(
+            description.paragraphs[1].text,
+            "The clay is mixed with H2O & sand before moulding."
+        );
+    }
+}